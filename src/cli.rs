@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use crate::solver::DEFAULT_STATE_LIMIT;
+
+pub const USAGE: &str = "\
+Usage: mushman [OPTIONS]
+
+Options:
+    --pack <PACK>        Play a .dat, .pack.ron or .pack.json pack from anywhere
+    --level <N>          Start on level N, counting from 0
+    --validate <PACK>    Check every level in a pack, then exit
+    --convert <FROM> <TO>
+                         Rewrite a pack as .dat, .pack.ron or .pack.json,
+                         then exit
+    --max-states <N>     How many positions the solver may try per level
+    --replay <FILE>      Watch a saved replay, on the pack given with --pack
+    --speed <N>          Play the replay N times faster
+    -h, --help           Print this message";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub pack: Option<PathBuf>,
+    pub level: usize,
+    pub validate: Option<PathBuf>,
+    pub convert: Option<(PathBuf, PathBuf)>,
+    pub max_states: usize,
+    pub replay: Option<PathBuf>,
+    pub speed: u32,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            pack: None,
+            level: 0,
+            validate: None,
+            convert: None,
+            max_states: DEFAULT_STATE_LIMIT,
+            replay: None,
+            speed: 1,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--pack" => parsed.pack = Some(value()?.into()),
+                "--level" => {
+                    let value = value()?;
+                    parsed.level = value
+                        .parse()
+                        .map_err(|_| format!("--level needs a number, not {value:?}"))?;
+                }
+                "--validate" => parsed.validate = Some(value()?.into()),
+                "--convert" => parsed.convert = Some((value()?.into(), value()?.into())),
+                "--max-states" => {
+                    let value = value()?;
+                    parsed.max_states = value
+                        .parse()
+                        .map_err(|_| format!("--max-states needs a number, not {value:?}"))?;
+                }
+                "--replay" => parsed.replay = Some(value()?.into()),
+                "--speed" => {
+                    let value = value()?;
+                    parsed.speed = value
+                        .parse()
+                        .ok()
+                        .filter(|speed| *speed > 0)
+                        .ok_or_else(|| format!("--speed needs a number above 0, not {value:?}"))?;
+                }
+                "-h" | "--help" => parsed.help = true,
+                _ => return Err(format!("Unknown argument {arg:?}")),
+            }
+        }
+        Ok(parsed)
+    }
+}
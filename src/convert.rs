@@ -0,0 +1,24 @@
+use std::{fs, path::Path};
+
+use crate::level::PackFormat;
+
+fn pack_format(path: &Path) -> Result<PackFormat, String> {
+    PackFormat::from_path(path).ok_or_else(|| {
+        format!(
+            "{}: expected a .dat, .pack.ron or .pack.json file",
+            path.display()
+        )
+    })
+}
+
+/// Rewrites the pack at `from` into `to`, picking both formats from the file
+/// extensions.
+pub fn convert_pack(from: &Path, to: &Path) -> Result<(), String> {
+    let input = pack_format(from)?;
+    let output = pack_format(to)?;
+    let bytes = fs::read(from).map_err(|e| format!("{}: {e}", from.display()))?;
+    let levels = input
+        .parse(&bytes)
+        .map_err(|e| format!("{}: {e}", from.display()))?;
+    fs::write(to, output.write(&levels)).map_err(|e| format!("{}: {e}", to.display()))
+}
@@ -0,0 +1,429 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{prelude::*, window::ReceivedCharacter};
+use ndarray::{Array2, Axis};
+
+use crate::{
+    events::LevelEvent,
+    image::TextureData,
+    level::{
+        parse_row, ActivePack, Cell, Coord, CurrentLevel, Dir, Level, Levels, PackChoice,
+        PackFormat, RawLevel, CELL_WIDTH,
+    },
+    ui::UiAssets,
+    validate::problems,
+    GameState, LevelRoot,
+};
+
+/// Largest grid the editor will grow to, in either direction.
+const MAX_SIZE: usize = 40;
+
+const HELP: &str = "Type a .dat letter to pick a brush, 1-5 and arrows for teleporters. \
+Left click paints, right click erases, Shift+arrows resize.\n\
+Tab: edit name/author  F2: save pack  F3: new level  F5: test-play  Esc: back";
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Editor::default())
+            .add_system(start_editing.in_schedule(OnEnter(GameState::Editor)))
+            .add_system(stop_editing.in_schedule(OnExit(GameState::Editor)))
+            .add_systems(
+                (editor_input, paint, draw_grid, draw_status)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Editor)),
+            );
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    #[default]
+    Grid,
+    Name,
+    Author,
+}
+
+/// The level being edited, and how the editor is being used.
+#[derive(Debug, Clone, Resource)]
+pub struct Editor {
+    level: Level,
+    brush: Cell,
+    /// Id and direction the next teleporter brush uses.
+    teleport: (u8, Dir),
+    focus: Focus,
+    status: String,
+    /// The map as it was last drawn, to only redraw when it changes.
+    drawn: Option<Array2<Cell>>,
+    /// Where Escape goes: back to the level, or to the menu it was opened
+    /// from.
+    pub(crate) back_to: GameState,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            level: blank_level(0),
+            brush: Cell::Wall,
+            teleport: (1, Dir::Up),
+            focus: Focus::default(),
+            status: String::new(),
+            drawn: None,
+            back_to: GameState::Playing,
+        }
+    }
+}
+
+impl Editor {
+    /// Turns the edited map into a playable level, the same way a pack would.
+    fn build(&self) -> Result<Level, String> {
+        self.raw()
+            .into_level(self.level.number)
+            .map_err(|e| format!("Cannot use this level: {e}"))
+    }
+
+    fn raw(&self) -> RawLevel {
+        RawLevel {
+            name: self.level.name.clone(),
+            author: self.level.author.clone(),
+            rows: self
+                .level
+                .map
+                .axis_iter(Axis(0))
+                .map(|row| row.to_vec())
+                .collect(),
+            info: self.level.info.clone(),
+        }
+    }
+
+    /// Puts the edited level into `levels`, replacing the one it was loaded
+    /// from or adding it at the end if it is new.
+    fn commit(&mut self, levels: &mut Levels) -> Result<(), String> {
+        let level = self.build()?;
+        match levels.levels.get_mut(level.number) {
+            Some(old) => *old = level,
+            None => levels.levels.push(level),
+        }
+        let problems = problems(&self.raw());
+        self.status = if problems.is_empty() {
+            String::new()
+        } else {
+            format!("Warning: {}. ", problems.join(", "))
+        };
+        Ok(())
+    }
+
+    fn resize(&mut self, rows: isize, columns: isize) {
+        let (height, width) = self.level.map.dim();
+        let height = (height as isize + rows).clamp(1, MAX_SIZE as isize) as usize;
+        let width = (width as isize + columns).clamp(1, MAX_SIZE as isize) as usize;
+        let old = &self.level.map;
+        self.level.map =
+            Array2::from_shape_fn((height, width), |p| old.get(p).copied().unwrap_or_default());
+    }
+
+    fn set(&mut self, c: Coord, cell: Cell) {
+        if self.level.map[*c] == cell {
+            return;
+        }
+        // A level only has one start, so painting a new one moves it.
+        if cell == Cell::Start {
+            self.level.map.map_inplace(|old| {
+                if *old == Cell::Start {
+                    *old = Cell::Empty;
+                }
+            });
+        }
+        self.level.map[*c] = cell;
+    }
+}
+
+fn blank_level(number: usize) -> Level {
+    Level {
+        name: "New level".to_string(),
+        number,
+        map: Array2::default((8, 10)),
+        ..Default::default()
+    }
+}
+
+/// Where F2 writes the pack: back over the one given with `--pack`, or next
+/// to the game for the bundled pack, which is left alone.
+fn save_path(choice: &PackChoice) -> PathBuf {
+    choice.path.clone().unwrap_or_else(|| "levels.dat".into())
+}
+
+fn start_editing(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    current_level: Res<CurrentLevel>,
+    ui_assets: Res<UiAssets>,
+    mut characters: ResMut<Events<ReceivedCharacter>>,
+) {
+    // Don't let the key that opened the editor pick a brush.
+    characters.clear();
+
+    editor.level = levels
+        .get(&pack)
+        .and_then(|levels| levels.levels.get(**current_level))
+        .cloned()
+        .unwrap_or_else(|| blank_level(**current_level));
+    editor.focus = Focus::Grid;
+    editor.status.clear();
+    editor.drawn = None;
+
+    commands.spawn((
+        EditorUi,
+        TextBundle::from_sections(vec![
+            TextSection::new(
+                "",
+                TextStyle {
+                    font: ui_assets.font.clone(),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+            );
+            3
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(8.0),
+                top: Val::Px(8.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.7)),
+    ));
+}
+
+fn stop_editing(mut commands: Commands, ui: Query<Entity, With<EditorUi>>) {
+    for entity in &ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(Debug, Copy, Clone, Component)]
+struct EditorUi;
+
+fn editor_input(
+    mut editor: ResMut<Editor>,
+    mut characters: EventReader<ReceivedCharacter>,
+    input: Res<Input<KeyCode>>,
+    pack: Res<ActivePack>,
+    choice: Res<PackChoice>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut levels: ResMut<Assets<Levels>>,
+    mut level_events: EventWriter<LevelEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if input.just_pressed(KeyCode::Tab) {
+        editor.focus = match editor.focus {
+            Focus::Grid => Focus::Name,
+            Focus::Name => Focus::Author,
+            Focus::Author => Focus::Grid,
+        };
+    }
+
+    match editor.focus {
+        Focus::Grid => {
+            for c in characters.iter().map(|event| event.char) {
+                let (id, dir) = editor.teleport;
+                editor.brush = match c {
+                    '1'..='5' => {
+                        let id = c as u8 - b'0';
+                        editor.teleport.0 = id;
+                        Cell::Teleport(id, dir)
+                    }
+                    't' => Cell::Teleport(id, dir),
+                    c => match parse_row(&c.to_string()) {
+                        Ok(cells) => cells[0],
+                        Err(_) => continue,
+                    },
+                };
+            }
+            let shift = input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+            for (key, dir) in [
+                (KeyCode::Up, Dir::Up),
+                (KeyCode::Down, Dir::Down),
+                (KeyCode::Left, Dir::Left),
+                (KeyCode::Right, Dir::Right),
+            ] {
+                if !input.just_pressed(key) {
+                    continue;
+                }
+                if shift {
+                    let (rows, columns) = dir.delta();
+                    editor.resize(rows, columns);
+                } else {
+                    editor.teleport.1 = dir;
+                    editor.brush = Cell::Teleport(editor.teleport.0, dir);
+                }
+            }
+        }
+        Focus::Name | Focus::Author => {
+            let focus = editor.focus;
+            let text = match focus {
+                Focus::Name => &mut editor.level.name,
+                _ => &mut editor.level.author,
+            };
+            text.extend(
+                characters
+                    .iter()
+                    .map(|event| event.char)
+                    .filter(|c| !c.is_control()),
+            );
+            if input.just_pressed(KeyCode::Back) {
+                text.pop();
+            }
+            if input.just_pressed(KeyCode::Return) {
+                editor.focus = Focus::Grid;
+            }
+        }
+    }
+
+    if input.just_pressed(KeyCode::F3) {
+        let number = levels.get(&pack).map_or(0, |levels| levels.levels.len());
+        editor.level = blank_level(number);
+        editor.status = format!("Started level {number}. ");
+    }
+
+    let save = input.just_pressed(KeyCode::F2);
+    let play = input.just_pressed(KeyCode::F5);
+    if save || play {
+        let Some(levels) = levels.get_mut(&pack) else {
+            editor.status = "The pack has not loaded yet. ".to_string();
+            return;
+        };
+        if let Err(e) = editor.commit(levels) {
+            editor.status = e;
+            return;
+        }
+        if save {
+            let path = save_path(&choice);
+            let format = PackFormat::from_path(&path).unwrap_or(PackFormat::Dat);
+            let saved = match fs::write(&path, format.write(levels)) {
+                // The game keeps playing the bundled pack from assets/.
+                Ok(()) if choice.path.is_none() => format!(
+                    "Saved to {0}, run the game with --pack {0} to play it. ",
+                    path.display()
+                ),
+                Ok(()) => format!("Saved to {}. ", path.display()),
+                Err(e) => format!("Could not save to {}: {e}. ", path.display()),
+            };
+            editor.status.push_str(&saved);
+        }
+        if play {
+            // Changing the pack reloads the current level once play resumes,
+            // so make that the edited one. Sending a LevelEvent as well would
+            // build the level twice in one frame.
+            **current_level = editor.level.number;
+            next_state.set(GameState::Playing);
+        }
+    }
+
+    if input.just_pressed(KeyCode::Escape) {
+        if editor.back_to == GameState::Playing {
+            level_events.send(LevelEvent(**current_level));
+        }
+        next_state.set(editor.back_to);
+    }
+}
+
+fn paint(
+    mut editor: ResMut<Editor>,
+    mouse: Res<Input<MouseButton>>,
+    window: Query<&Window>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    let cell = if mouse.pressed(MouseButton::Left) {
+        editor.brush
+    } else if mouse.pressed(MouseButton::Right) {
+        Cell::Empty
+    } else {
+        return;
+    };
+    let (camera, camera_transform) = camera.single();
+    let Some(position) = window
+        .single()
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    else {
+        return;
+    };
+
+    // Tiles are centred on multiples of the cell width, rows going down.
+    let column = (position.x / CELL_WIDTH).round();
+    let row = (-position.y / CELL_WIDTH).round();
+    let (height, width) = editor.level.map.dim();
+    if row < 0.0 || column < 0.0 || row as usize >= height || column as usize >= width {
+        return;
+    }
+    editor.set(Coord::new((row as usize, column as usize)), cell);
+}
+
+/// Redraws the grid whenever the map changes, using the same sprites as the
+/// game. Starts are drawn with the player so they can be seen.
+fn draw_grid(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    root: Query<Entity, With<LevelRoot>>,
+    texture_data: Res<TextureData>,
+) {
+    if editor.drawn.as_ref() == Some(&editor.level.map) {
+        return;
+    }
+    editor.drawn = Some(editor.level.map.clone());
+
+    for root in &root {
+        commands.entity(root).despawn_recursive();
+    }
+    let atlas = texture_data.atlas.clone();
+    commands
+        .spawn((LevelRoot, SpatialBundle::default()))
+        .with_children(|parent| {
+            for ((j, i), cell) in editor.level.map.indexed_iter() {
+                cell.construct(parent, Coord::new((j, i)), atlas.clone());
+                if *cell == Cell::Start {
+                    parent.spawn(SpriteSheetBundle {
+                        transform: Transform::from_xyz(
+                            i as f32 * CELL_WIDTH,
+                            j as f32 * -CELL_WIDTH,
+                            1.0,
+                        ),
+                        texture_atlas: atlas.clone(),
+                        sprite: TextureAtlasSprite::new(0),
+                        ..Default::default()
+                    });
+                }
+            }
+        });
+}
+
+fn draw_status(editor: Res<Editor>, mut text: Query<&mut Text, With<EditorUi>>) {
+    if !editor.is_changed() {
+        return;
+    }
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let marker = |focus| if editor.focus == focus { "> " } else { "" };
+    let (height, width) = editor.level.map.dim();
+    text.sections[0].value = format!(
+        "Level {}  {width} x {height}  brush: {} ({:?})\n",
+        editor.level.number, editor.brush, editor.brush
+    );
+    text.sections[1].value = format!(
+        "{}Name: {}\n{}Author: {}\n",
+        marker(Focus::Name),
+        editor.level.name,
+        marker(Focus::Author),
+        editor.level.author
+    );
+    text.sections[2].value = format!("{}\n{HELP}", editor.status);
+}
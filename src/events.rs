@@ -1,16 +1,49 @@
-use bevy::{app::AppExit, prelude::*};
+use std::time::Duration;
 
-use crate::{GameState, level::Coord};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::{Dir, Inventory, Item, LevelMap},
+    save::LevelClock,
+    score::{Par, Score},
+    GameState,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct DeathEvent(pub String);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[allow(dead_code)]
+/// The player reached the exit of the current level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CompleteEvent;
+
+/// How the player last died, for the game over screen.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct DeathMessage(pub String);
+
+/// How the level that was just finished went.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct LevelSummary {
+    pub number: usize,
+    pub name: String,
+    pub moves: usize,
+    pub pushes: usize,
+    pub items_used: usize,
+    pub par: Option<usize>,
+    pub time: Duration,
+    pub items: Vec<(Item, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Sound {
     PlayerDie,
+    /// The player tried to walk somewhere they can't.
     HitWall,
     Explosion,
+    Pickup,
+    Push,
+    Gunshot,
+    Teleport,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -19,46 +52,90 @@ pub struct SoundEvent {
 }
 
 pub struct MovementEvent {
-    pub pos: Coord,
+    pub dir: Dir,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct LevelEvent(pub usize);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryEvent {
+    Undo,
+    Redo,
+}
+
+/// Moving around a menu, from whichever device the player is using.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MenuEvent {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
 pub struct EventPlugin;
 
 impl Plugin for EventPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<DeathEvent>()
+            .add_event::<CompleteEvent>()
             .add_event::<SoundEvent>()
             .add_event::<MovementEvent>()
             .add_event::<LevelEvent>()
-            .add_system(player_death.in_set(OnUpdate(GameState::Playing)));
+            .add_event::<HistoryEvent>()
+            .add_event::<MenuEvent>()
+            .insert_resource(DeathMessage::default())
+            .insert_resource(LevelSummary::default())
+            .add_system(player_death.in_set(OnUpdate(GameState::Playing)))
+            .add_system(level_complete.in_set(OnUpdate(GameState::Playing)));
     }
 }
 
 fn player_death(
-    mut _commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
     mut events: EventReader<DeathEvent>,
-    mut app_exit_events: EventWriter<AppExit>,
+    mut message: ResMut<DeathMessage>,
 ) {
     for event in events.iter() {
-        // commands.spawn_bundle(TextBundle {
-        //     text: Text::with_section(
-        //         event.0.clone(),
-        //         TextStyle {
-        //             font: Asset::<Font>::load("fonts/FiraSans-Bold.ttf"),
-        //             font_size: 40.0,
-        //             color: Color::WHITE,
-        //         },
-        //         TextAlignment::default(),
-        //     ),
-        //     ..Default::default()
-        // });
-        // TODO: Handle GameOver state properly
         log::info!("{}", event.0);
+        message.0 = event.0.clone();
         next_state.set(GameState::GameOver);
-        app_exit_events.send(AppExit);
     }
 }
+
+fn level_complete(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut events: EventReader<CompleteEvent>,
+    mut summary: ResMut<LevelSummary>,
+    level_map: Res<LevelMap>,
+    score: Res<Score>,
+    par: Res<Par>,
+    inventory: Res<Inventory>,
+    clock: Res<LevelClock>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    *summary = LevelSummary {
+        number: level_map.number,
+        name: level_map.name.clone(),
+        moves: score.moves,
+        pushes: score.pushes,
+        items_used: score.items_used,
+        par: par.moves,
+        time: clock.elapsed(),
+        items: Item::ALL
+            .into_iter()
+            .map(|item| (item, inventory.count(item)))
+            .filter(|(_, count)| *count > 0)
+            .collect(),
+    };
+    log::info!(
+        "Finished level {} in {} moves",
+        summary.number,
+        summary.moves
+    );
+    next_state.set(GameState::LevelComplete);
+}
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use crate::{
+    events::{HistoryEvent, LevelEvent},
+    image::TextureData,
+    level::{
+        move_player, replace_tile, Coord, Inventory, Level, LevelEntities, LevelMap, CELL_WIDTH,
+    },
+    score::Score,
+    GameState, LevelRoot, Player,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(History::default())
+            .add_system(clear_history.in_set(OnUpdate(GameState::Playing)))
+            .add_system(
+                travel_history
+                    .in_set(OnUpdate(GameState::Playing))
+                    .after(clear_history)
+                    // A fixed order, so replays record what really happened.
+                    .after(move_player),
+            );
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    level: Level,
+    inventory: Inventory,
+    score: Score,
+}
+
+/// Every state the current level has been in, so moves can be taken back.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    /// Remembers the state from before a move. Making a new move forgets
+    /// anything that was undone.
+    pub fn record(&mut self, level: Level, inventory: Inventory, score: Score) {
+        self.undo.push(Snapshot {
+            level,
+            inventory,
+            score,
+        });
+        self.redo.clear();
+    }
+
+    pub fn undo(
+        &mut self,
+        level: &mut Level,
+        inventory: &mut Inventory,
+        score: &mut Score,
+    ) -> bool {
+        Self::swap(&mut self.undo, &mut self.redo, level, inventory, score)
+    }
+
+    pub fn redo(
+        &mut self,
+        level: &mut Level,
+        inventory: &mut Inventory,
+        score: &mut Score,
+    ) -> bool {
+        Self::swap(&mut self.redo, &mut self.undo, level, inventory, score)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    fn swap(
+        from: &mut Vec<Snapshot>,
+        to: &mut Vec<Snapshot>,
+        level: &mut Level,
+        inventory: &mut Inventory,
+        score: &mut Score,
+    ) -> bool {
+        let Some(snapshot) = from.pop() else {
+            return false;
+        };
+        to.push(Snapshot {
+            level: std::mem::replace(level, snapshot.level),
+            inventory: std::mem::replace(inventory, snapshot.inventory),
+            score: std::mem::replace(score, snapshot.score),
+        });
+        true
+    }
+}
+
+fn clear_history(mut level_events: EventReader<LevelEvent>, mut history: ResMut<History>) {
+    if level_events.iter().count() > 0 {
+        history.clear();
+    }
+}
+
+fn travel_history(
+    mut commands: Commands,
+    mut history_events: EventReader<HistoryEvent>,
+    mut history: ResMut<History>,
+    mut level_map: ResMut<LevelMap>,
+    mut level_entities: ResMut<LevelEntities>,
+    mut inventory: ResMut<Inventory>,
+    mut score: ResMut<Score>,
+    mut transform: Query<&mut Transform, With<Player>>,
+    root: Query<Entity, With<LevelRoot>>,
+    texture_data: Res<TextureData>,
+) {
+    for event in history_events.iter() {
+        let before = level_map.map.clone();
+        let travelled = match event {
+            HistoryEvent::Undo => history.undo(&mut level_map, &mut inventory, &mut score),
+            HistoryEvent::Redo => history.redo(&mut level_map, &mut inventory, &mut score),
+        };
+        if !travelled {
+            continue;
+        }
+
+        let root = root.single();
+        for ((j, i), cell) in level_map.map.indexed_iter() {
+            if before[(j, i)] != *cell {
+                replace_tile(
+                    &mut commands,
+                    &mut level_entities,
+                    root,
+                    texture_data.atlas.clone(),
+                    Coord::new((j, i)),
+                    *cell,
+                );
+            }
+        }
+        *transform.single_mut() = Transform::from_xyz(
+            level_map.player_pos.1 as f32 * CELL_WIDTH,
+            level_map.player_pos.0 as f32 * -CELL_WIDTH,
+            1.0,
+        );
+    }
+}
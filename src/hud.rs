@@ -0,0 +1,168 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    image::TextureData,
+    level::{Inventory, Item},
+    score::{Par, Score},
+    ui::UiAssets,
+    GameState,
+};
+
+/// Room each item gets in the HUD, icon and count together.
+const SLOT_WIDTH: f32 = 80.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_hud.in_schedule(OnExit(GameState::Loading)))
+            .add_system(place_hud)
+            .add_system(update_hud)
+            .add_system(update_score);
+    }
+}
+
+#[derive(Debug, Copy, Clone, Component)]
+struct Hud;
+
+#[derive(Debug, Copy, Clone, Component)]
+struct HudIcon(Item);
+
+#[derive(Debug, Copy, Clone, Component)]
+struct HudCount(Item);
+
+#[derive(Debug, Copy, Clone, Component)]
+struct HudScore;
+
+// Keys first, since they matter in the most levels.
+const HUD_ITEMS: [Item; 4] = [Item::Key, Item::Cement, Item::Money, Item::Oxygen];
+
+/// Hangs the HUD off the camera, so it stays put however the level is drawn.
+fn spawn_hud(
+    mut commands: Commands,
+    camera: Query<Entity, With<Camera>>,
+    texture_data: Res<TextureData>,
+    ui_assets: Res<UiAssets>,
+) {
+    let style = TextStyle {
+        font: ui_assets.font.clone(),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+    let hud = commands
+        .spawn((Hud, SpatialBundle::default()))
+        .with_children(|parent| {
+            parent.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                    custom_size: Some(Vec2::new(SLOT_WIDTH * HUD_ITEMS.len() as f32, 72.0)),
+                    anchor: Anchor::TopLeft,
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(-20.0, 20.0, 0.0),
+                ..Default::default()
+            });
+            for (i, item) in HUD_ITEMS.into_iter().enumerate() {
+                let x = i as f32 * SLOT_WIDTH;
+                parent.spawn((
+                    HudIcon(item),
+                    SpriteSheetBundle {
+                        texture_atlas: texture_data.atlas.clone(),
+                        sprite: TextureAtlasSprite::new(item.cell().indices()[0]),
+                        transform: Transform::from_xyz(x, 0.0, 0.1),
+                        ..Default::default()
+                    },
+                ));
+                parent.spawn((
+                    HudCount(item),
+                    Text2dBundle {
+                        text: Text::from_section("x 0", style.clone()),
+                        text_anchor: Anchor::CenterLeft,
+                        transform: Transform::from_xyz(x + 20.0, 0.0, 0.1),
+                        ..Default::default()
+                    },
+                ));
+            }
+            // Moves and the rest go underneath the items.
+            parent.spawn((
+                HudScore,
+                Text2dBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 16.0,
+                            ..style.clone()
+                        },
+                    ),
+                    text_anchor: Anchor::CenterLeft,
+                    transform: Transform::from_xyz(-12.0, -32.0, 0.1),
+                    ..Default::default()
+                },
+            ));
+        })
+        .id();
+    commands.entity(camera.single()).add_child(hud);
+}
+
+/// Keeps the HUD in the top left corner of the window, and only shows it
+/// while there is a level to play.
+fn place_hud(
+    window: Query<&Window>,
+    state: Res<State<GameState>>,
+    mut hud: Query<(&mut Transform, &mut Visibility), With<Hud>>,
+) {
+    let Ok((mut transform, mut visibility)) = hud.get_single_mut() else {
+        return;
+    };
+    let window = window.single();
+    // Just in front of the camera, which sits above everything in the level.
+    *transform = Transform::from_xyz(
+        -window.width() / 2.0 + 36.0,
+        window.height() / 2.0 - 28.0,
+        -1.0,
+    );
+    *visibility = match state.0 {
+        GameState::Playing | GameState::Paused | GameState::GameOver | GameState::LevelComplete => {
+            Visibility::Inherited
+        }
+        _ => Visibility::Hidden,
+    };
+}
+
+fn update_hud(
+    inventory: Res<Inventory>,
+    mut counts: Query<(&HudCount, &mut Text)>,
+    mut icons: Query<(&HudIcon, &mut TextureAtlasSprite)>,
+) {
+    if !inventory.is_changed() {
+        return;
+    }
+    for (HudCount(item), mut text) in &mut counts {
+        text.sections[0].value = format!("x {}", inventory.count(*item));
+    }
+    // Items the player has run out of are greyed out.
+    for (HudIcon(item), mut sprite) in &mut icons {
+        sprite.color = if inventory.count(*item) > 0 {
+            Color::WHITE
+        } else {
+            Color::rgba(1.0, 1.0, 1.0, 0.35)
+        };
+    }
+}
+
+fn update_score(score: Res<Score>, par: Res<Par>, mut text: Query<&mut Text, With<HudScore>>) {
+    if !(score.is_changed() || par.is_changed()) {
+        return;
+    }
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let par = par
+        .moves
+        .map(|moves| format!(" / {moves}"))
+        .unwrap_or_default();
+    text.sections[0].value = format!(
+        "Moves {}{par}   Pushes {}   Used {}",
+        score.moves, score.pushes, score.items_used
+    );
+}
@@ -1,8 +1,10 @@
 use bevy::prelude::*;
 
 use crate::{
-    events::{LevelEvent, MovementEvent},
-    level::{load_level, CurrentLevel, LevelMap},
+    editor::Editor,
+    events::{HistoryEvent, LevelEvent, MenuEvent, MovementEvent},
+    level::{load_level, move_player, CurrentLevel, Dir},
+    replay::not_replaying,
     GameState,
 };
 
@@ -10,63 +12,165 @@ pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(level_input.in_set(OnUpdate(GameState::Playing)))
-            // .add_system(next_level.in_set(OnUpdate(GameState::Playing)))
-            .add_system(
-                player_input
-                    .in_set(OnUpdate(GameState::Playing))
-                    .after(load_level),
-            );
+        app.add_system(
+            level_input
+                .in_set(OnUpdate(GameState::Playing))
+                .run_if(not_replaying),
+        )
+        .add_system(restart_input.in_set(OnUpdate(GameState::GameOver)))
+        .add_system(
+            history_input
+                .in_set(OnUpdate(GameState::Playing))
+                .before(move_player)
+                .run_if(not_replaying),
+        )
+        .add_system(
+            editor_input
+                .in_set(OnUpdate(GameState::Playing))
+                .run_if(not_replaying),
+        )
+        .add_system(pause_input.in_set(OnUpdate(GameState::Playing)))
+        .add_system(menu_input.in_set(OnUpdate(GameState::Menu)))
+        .add_system(menu_input.in_set(OnUpdate(GameState::Paused)))
+        .add_system(menu_input.in_set(OnUpdate(GameState::LevelComplete)))
+        // .add_system(next_level.in_set(OnUpdate(GameState::Playing)))
+        .add_system(
+            player_input
+                .in_set(OnUpdate(GameState::Playing))
+                .after(load_level)
+                .before(move_player)
+                .run_if(not_replaying),
+        );
     }
 }
 
 fn level_input(
     input: Res<Input<KeyCode>>,
-    mut current_level: ResMut<CurrentLevel>,
+    current_level: Res<CurrentLevel>,
     mut level_events: EventWriter<LevelEvent>,
 ) {
     if input.just_pressed(KeyCode::J) {
-        **current_level += 1;
-        level_events.send(LevelEvent(**current_level));
+        level_events.send(LevelEvent(**current_level + 1));
     } else if input.just_pressed(KeyCode::K) {
         if **current_level > 0 {
-            **current_level -= 1;
-            level_events.send(LevelEvent(**current_level));
+            level_events.send(LevelEvent(**current_level - 1));
         }
+    } else if input.just_pressed(KeyCode::R) {
+        level_events.send(LevelEvent(**current_level));
     }
 }
 
-// fn next_level(mut level_events: EventReader<LevelEvent>, mut current_level: ResMut<CurrentLevel>) {
-//     for LevelEvent(level) in level_events.iter() {
-//         **current_level = *level;
-//     }
-// }
-
-fn player_input(
+fn restart_input(
     input: Res<Input<KeyCode>>,
-    level_map: Res<LevelMap>,
-    mut movement_events: EventWriter<MovementEvent>,
+    current_level: Res<CurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut level_events: EventWriter<LevelEvent>,
 ) {
-    let (mut dx, mut dy): (isize, isize) = (0, 0);
-    if input.just_pressed(KeyCode::W) {
-        dy -= 1;
+    if input.just_pressed(KeyCode::R) {
+        level_events.send(LevelEvent(**current_level));
+        next_state.set(GameState::Playing);
     }
-    if input.just_pressed(KeyCode::D) {
-        dx += 1;
+}
+
+fn history_input(input: Res<Input<KeyCode>>, mut history_events: EventWriter<HistoryEvent>) {
+    if input.just_pressed(KeyCode::Z) {
+        history_events.send(HistoryEvent::Undo);
+    } else if input.just_pressed(KeyCode::X) {
+        history_events.send(HistoryEvent::Redo);
     }
-    if input.just_pressed(KeyCode::A) {
-        dx -= 1;
+}
+
+fn editor_input(
+    input: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if input.just_pressed(KeyCode::E) {
+        editor.back_to = GameState::Playing;
+        next_state.set(GameState::Editor);
     }
-    if input.just_pressed(KeyCode::S) {
-        dy += 1;
+}
+
+fn pause_input(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let start = gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start)));
+    if keys.just_pressed(KeyCode::Escape) || start {
+        next_state.set(GameState::Paused);
     }
+}
 
-    let level_map = level_map.as_ref();
-    let (height, width) = &level_map.map.dim();
-    let mut pos = level_map.player_pos;
-    pos.1 = (pos.1 as isize + dx).clamp(0, (*width as isize - 1).max(0)) as usize;
-    pos.0 = (pos.0 as isize + dy).clamp(0, (*height as isize - 1).max(0)) as usize;
-    if pos != level_map.player_pos {
-        movement_events.send(MovementEvent { pos });
+fn menu_input(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut menu_events: EventWriter<MenuEvent>,
+) {
+    let bindings = [
+        (
+            MenuEvent::Up,
+            [KeyCode::Up, KeyCode::W],
+            GamepadButtonType::DPadUp,
+        ),
+        (
+            MenuEvent::Down,
+            [KeyCode::Down, KeyCode::S],
+            GamepadButtonType::DPadDown,
+        ),
+        (
+            MenuEvent::Left,
+            [KeyCode::Left, KeyCode::A],
+            GamepadButtonType::DPadLeft,
+        ),
+        (
+            MenuEvent::Right,
+            [KeyCode::Right, KeyCode::D],
+            GamepadButtonType::DPadRight,
+        ),
+        (
+            MenuEvent::Select,
+            [KeyCode::Return, KeyCode::Space],
+            GamepadButtonType::South,
+        ),
+        (
+            MenuEvent::Back,
+            [KeyCode::Escape, KeyCode::Back],
+            GamepadButtonType::East,
+        ),
+    ];
+    for (event, keys_for, button) in bindings {
+        let pressed = keys.any_just_pressed(keys_for)
+            || gamepads
+                .iter()
+                .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)));
+        if pressed {
+            menu_events.send(event);
+        }
     }
 }
+
+// fn next_level(mut level_events: EventReader<LevelEvent>, mut current_level: ResMut<CurrentLevel>) {
+//     for LevelEvent(level) in level_events.iter() {
+//         **current_level = *level;
+//     }
+// }
+
+fn player_input(input: Res<Input<KeyCode>>, mut movement_events: EventWriter<MovementEvent>) {
+    let dir = if input.just_pressed(KeyCode::W) {
+        Dir::Up
+    } else if input.just_pressed(KeyCode::D) {
+        Dir::Right
+    } else if input.just_pressed(KeyCode::A) {
+        Dir::Left
+    } else if input.just_pressed(KeyCode::S) {
+        Dir::Down
+    } else {
+        return;
+    };
+    movement_events.send(MovementEvent { dir });
+}
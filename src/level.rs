@@ -1,25 +1,39 @@
-use std::ops::{ControlFlow, Deref, DerefMut};
+use std::{
+    fs,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
 
 use bevy::{
-    asset::{AssetLoader, LoadedAsset},
+    asset::{AssetLoader, LoadState, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
     utils::HashMap,
 };
 use bevy_asset_loader::prelude::*;
 use ndarray::{Array2, Axis};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    events::{DeathEvent, LevelEvent, MovementEvent, SoundEvent},
+    events::{CompleteEvent, DeathEvent, LevelEvent, MovementEvent, SoundEvent},
+    history::History,
     image::{Explosion, TextureData, EXPLOSION_INDICES},
+    score::Score,
     GameState, LevelRoot, Player,
 };
 
 use self::parse::parse_levels;
+pub(crate) use self::parse::{parse_row, RawLevel};
+pub(crate) use self::rules::StepOutcome;
+pub(crate) use self::structured::PackFormat;
+use self::structured::StructuredLevelsLoader;
 
 mod parse;
+mod rules;
+mod structured;
+mod write;
 
-const CELL_WIDTH: f32 = 32.0;
+pub(crate) const CELL_WIDTH: f32 = 32.0;
 
 // Case "b"
 //     SetSq Pos, "bomb"
@@ -120,8 +134,12 @@ impl Cell {
         }
     }
 
-    // TODO: Pass player inventory here, to see if they can pass
-    pub fn action(&self) -> CellAction {
+    /// What walking onto this cell does, given what the player is carrying.
+    pub fn action(&self, inventory: &Inventory) -> CellAction {
+        self.rule().resolve(inventory)
+    }
+
+    fn rule(&self) -> CellAction {
         match self {
             Cell::Empty => CellAction::Nothing,
             Cell::Wall => CellAction::Block,
@@ -184,7 +202,7 @@ impl Cell {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Dir {
     Up,
     Down,
@@ -192,6 +210,20 @@ pub enum Dir {
     Right,
 }
 
+impl Dir {
+    pub const ALL: [Dir; 4] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+
+    /// The `(row, column)` offset of one step in this direction.
+    pub fn delta(&self) -> (isize, isize) {
+        match self {
+            Dir::Up => (-1, 0),
+            Dir::Down => (1, 0),
+            Dir::Left => (0, -1),
+            Dir::Right => (0, 1),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub enum CellAction {
     #[default]
@@ -211,6 +243,33 @@ pub enum CellAction {
     NextLevel,
 }
 
+impl CellAction {
+    /// Picks the branch of every `Consume` that `inventory` can afford, so a
+    /// resolved `Consume` always has its item available and only its
+    /// `success` matters.
+    pub fn resolve(self, inventory: &Inventory) -> CellAction {
+        match self {
+            CellAction::Consume {
+                item,
+                fail,
+                success,
+            } => {
+                let mut remaining = inventory.clone();
+                if remaining.take(item) {
+                    CellAction::Consume {
+                        item,
+                        fail,
+                        success: Box::new(success.resolve(&remaining)),
+                    }
+                } else {
+                    fail.resolve(inventory)
+                }
+            }
+            action => action,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Item {
     Key,
@@ -221,19 +280,67 @@ pub enum Item {
     // Bomb,
 }
 
+impl Item {
+    pub const ALL: [Item; 4] = [Item::Key, Item::Oxygen, Item::Cement, Item::Money];
+
+    /// The cell this item is picked up from, which is also how it is drawn.
+    pub fn cell(&self) -> Cell {
+        match self {
+            Item::Key => Cell::Key,
+            Item::Oxygen => Cell::Oxygen,
+            Item::Cement => Cell::Cement,
+            Item::Money => Cell::Money,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Resource, Default)]
 pub struct Inventory {
     pub(crate) map: HashMap<Item, usize>,
 }
 
-#[derive(Debug, Default, Clone)]
+impl Inventory {
+    pub fn count(&self, item: Item) -> usize {
+        self.map.get(&item).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, item: Item, amount: usize) {
+        *self.map.entry(item).or_insert(0) += amount;
+    }
+
+    /// Uses up one `item`, if there is one to use.
+    pub fn take(&mut self, item: Item) -> bool {
+        match self.map.get_mut(&item) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Level {
-    name: String,
-    author: String,
+    pub(crate) name: String,
+    pub(crate) author: String,
     pub(crate) number: usize,
     pub(crate) map: Array2<Cell>,
+    /// How many cells each row had in the pack, before short rows were
+    /// padded out to the width of the map.
+    pub(crate) row_widths: Vec<usize>,
     pub(crate) start_pos: Coord,
     pub(crate) player_pos: Coord,
+    pub(crate) info: LevelInfo,
+}
+
+/// Extra details about a level that only structured packs can hold.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LevelInfo {
+    pub difficulty: Option<u8>,
+    /// Number of moves a good solution takes.
+    pub par: Option<usize>,
+    pub hints: Vec<String>,
 }
 
 impl Level {
@@ -262,13 +369,31 @@ impl Level {
         }
         Some(Coord::new((j as usize, i as usize)))
     }
+
+    /// Finds the teleporter paired with the one at `c`.
+    ///
+    /// Teleporters with the same id form a ring in reading order: each one
+    /// sends the player to the next one after it, wrapping around.
+    pub fn teleport_partner(&self, c: Coord, id: u8) -> Option<Coord> {
+        let partners: Vec<Coord> = self
+            .map
+            .indexed_iter()
+            .filter(|(p, cell)| matches!(cell, Cell::Teleport(n, _) if *n == id) && p != &*c)
+            .map(|(p, _)| Coord::new(p))
+            .collect();
+        partners
+            .iter()
+            .find(|p| ***p > *c)
+            .or(partners.first())
+            .copied()
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Coord((usize, usize));
 
 impl Coord {
-    fn new(pos: (usize, usize)) -> Self {
+    pub(crate) fn new(pos: (usize, usize)) -> Self {
         Self(pos)
     }
 }
@@ -288,11 +413,38 @@ impl DerefMut for Coord {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Default, Resource, TypeUuid, AssetCollection)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Resource, TypeUuid, AssetCollection)]
 #[uuid = "509449b4-8862-4b9c-ad82-ff8e0a2cbd15"]
 pub struct Levels {
-    checksum: u32,
-    levels: Vec<Level>,
+    pub(crate) checksum: u32,
+    pub(crate) levels: Vec<Level>,
+}
+
+impl Levels {
+    /// Tells packs apart by their levels, whichever format they were loaded
+    /// from. The checksum in the header can't do that, its algorithm is
+    /// unknown and structured packs leave it at 0. This writes out the whole
+    /// pack, so use [`PackFingerprint`] for the one being played.
+    pub fn fingerprint(&self) -> u32 {
+        let text: String = self.levels.iter().map(Level::to_string).collect();
+        crc32(text.as_bytes())
+    }
+}
+
+/// The CRC-32 used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
 }
 
 #[derive(Debug, Clone, Default, Copy)]
@@ -306,7 +458,7 @@ impl AssetLoader for LevelsLoader {
     ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
             let levels = parse_levels(bytes)
-                .map_err(|e| bevy::asset::Error::msg(format!("Error loading levels: {e:?}")))?;
+                .map_err(|e| bevy::asset::Error::msg(format!("Error loading levels: {e}")))?;
             load_context.set_default_asset(LoadedAsset::new(levels));
             Ok(())
         })
@@ -323,6 +475,31 @@ pub struct LevelData {
     handle: Handle<Levels>,
 }
 
+/// The pack to play and the level to start on, from the command line. With
+/// no path the bundled levels.dat is played.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct PackChoice {
+    pub path: Option<PathBuf>,
+    pub level: usize,
+}
+
+/// The pack being played.
+#[derive(Debug, Clone, Default, Resource, Deref)]
+pub struct ActivePack(Handle<Levels>);
+
+/// The [`Levels::fingerprint`] of the pack being played, worked out again only
+/// when it loads or changes.
+#[derive(Debug, Clone, Copy, Default, Resource, Deref)]
+pub struct PackFingerprint(Option<u32>);
+
+/// A level to start as soon as the pack has loaded.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct PendingLevel(pub Option<usize>);
+
+/// Why the chosen pack could not be played.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct LoadError(pub String);
+
 #[derive(Debug, Clone, Default, Resource, Deref, DerefMut)]
 pub struct CurrentLevel(usize);
 
@@ -338,26 +515,150 @@ impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
         app.add_asset::<Levels>()
             .init_asset_loader::<LevelsLoader>()
+            .init_asset_loader::<StructuredLevelsLoader>()
+            .init_resource::<PackChoice>()
+            .insert_resource(ActivePack::default())
+            .insert_resource(PackFingerprint::default())
+            .insert_resource(PendingLevel::default())
             .insert_resource(CurrentLevel(0))
             .insert_resource(LevelMap::default())
             .insert_resource(LevelEntities::default())
             .insert_resource(Inventory::default())
             .add_collection_to_loading_state::<_, LevelData>(GameState::Loading)
-            .add_system(start_game.in_schedule(OnEnter(GameState::Playing)))
+            .add_startup_system(load_pack)
+            .add_system(fingerprint_pack)
+            .add_system(start_game.in_schedule(OnExit(GameState::Loading)))
+            .add_system(
+                start_pack
+                    .in_set(OnUpdate(GameState::Playing))
+                    .before(load_level),
+            )
+            .add_system(
+                reload_pack
+                    .in_set(OnUpdate(GameState::Playing))
+                    .before(load_level),
+            )
             .add_system(load_level.in_set(OnUpdate(GameState::Playing)))
             .add_system(move_player.in_set(OnUpdate(GameState::Playing)));
     }
 }
 
-fn start_game(mut commands: Commands, mut level_events: EventWriter<LevelEvent>) {
-    level_events.send(LevelEvent(0));
-    commands.insert_resource(CurrentLevel(0));
+fn load_pack(
+    choice: Res<PackChoice>,
+    asset_server: Res<AssetServer>,
+    mut pack: ResMut<ActivePack>,
+) {
+    if let Some(path) = &choice.path {
+        pack.0 = asset_server.load(path.as_path());
+    }
+}
+
+fn start_game(
+    mut commands: Commands,
+    choice: Res<PackChoice>,
+    level_data: Res<LevelData>,
+    mut pack: ResMut<ActivePack>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if choice.path.is_none() {
+        pack.0 = level_data.handle.clone();
+    }
+    commands.insert_resource(CurrentLevel(choice.level));
+    // A pack or level picked on the command line skips the menu.
+    if choice.path.is_some() || choice.level > 0 {
+        commands.insert_resource(PendingLevel(Some(choice.level)));
+        next_state.set(GameState::Playing);
+    }
+}
+
+/// Starts the chosen level once its pack has loaded, or shows why it cannot.
+fn start_pack(
+    mut commands: Commands,
+    mut pending: ResMut<PendingLevel>,
+    pack: Res<ActivePack>,
+    choice: Res<PackChoice>,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<Levels>>,
+    mut level_events: EventWriter<LevelEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = pending.0 else {
+        return;
+    };
+    let error = match (levels.get(&pack.0), asset_server.get_load_state(&pack.0)) {
+        (Some(levels), _) if level < levels.levels.len() => {
+            level_events.send(LevelEvent(level));
+            None
+        }
+        (Some(levels), _) => Some(format!(
+            "There is no level {level}, the pack only has {}",
+            levels.levels.len()
+        )),
+        (None, LoadState::Failed) => Some(match &choice.path {
+            Some(path) => describe_failure(path),
+            None => "The bundled levels.dat failed to load".to_string(),
+        }),
+        (None, _) => return,
+    };
+    pending.0 = None;
+    if let Some(error) = error {
+        log::error!("{error}");
+        commands.insert_resource(LoadError(error));
+        next_state.set(GameState::Error);
+    }
+}
+
+/// The asset server only logs why an asset failed, so read the pack again to
+/// say what is wrong with it.
+fn describe_failure(path: &Path) -> String {
+    let format = PackFormat::from_path(path).unwrap_or(PackFormat::Dat);
+    match fs::read(path).map(|bytes| format.parse(&bytes)) {
+        Err(e) => format!("{}: {e}", path.display()),
+        Ok(Err(e)) => format!("{}: {e}", path.display()),
+        Ok(Ok(_)) => format!(
+            "{}: not a level pack, expected a .dat, .pack.ron or .pack.json file",
+            path.display()
+        ),
+    }
+}
+
+pub(crate) fn fingerprint_pack(
+    mut asset_events: EventReader<AssetEvent<Levels>>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    mut fingerprint: ResMut<PackFingerprint>,
+) {
+    let loaded = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == pack.0,
+        AssetEvent::Removed { .. } => false,
+    });
+    if loaded || pack.is_changed() {
+        fingerprint.0 = levels.get(&pack).map(Levels::fingerprint);
+    }
+}
+
+/// Reloads the current level when its pack changes on disk, so edits made in
+/// a text editor show up straight away. A pack that no longer parses keeps
+/// the levels it had.
+fn reload_pack(
+    mut asset_events: EventReader<AssetEvent<Levels>>,
+    pack: Res<ActivePack>,
+    current_level: Res<CurrentLevel>,
+    mut level_events: EventWriter<LevelEvent>,
+) {
+    let modified = asset_events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { handle } if *handle == pack.0));
+    if modified {
+        log::info!("Level pack changed, reloading level {}", **current_level);
+        level_events.send(LevelEvent(**current_level));
+    }
 }
 
 pub(crate) fn load_level(
     mut commands: Commands,
     mut level_events: EventReader<LevelEvent>,
-    level_data: Res<LevelData>,
+    pack: Res<ActivePack>,
     levels: Res<Assets<Levels>>,
     texture_data: Res<TextureData>,
     root: Query<(Entity, &LevelRoot)>,
@@ -367,393 +668,275 @@ pub(crate) fn load_level(
     //     return;
     // }
 
-    for LevelEvent(current_level) in level_events.iter() {
-        let (root, _) = root
-            .get_single()
-            .expect("Always have a root outside of this system");
-        let levels = levels
-            .get(&level_data.handle)
-            .expect("Only loaded levels by this point");
-        let mut level = levels.levels[*current_level].clone();
-        level.player_pos = level.start_pos;
-        let mut window = window.single_mut();
-        window.title = format!("Level: {}, by {}", level.name, level.author);
-        let (height, width) = level.map.dim();
-
-        log::info!("Changing level: {}", *current_level);
-        log::info!("Width x Height: {width} x {height}");
-        log::info!("Player start: {:?}", level.start_pos);
-
-        let mut entities = HashMap::default();
-        let atlas = texture_data.atlas.clone();
-        commands.insert_resource(LevelMap(level.clone()));
-        commands.entity(root).despawn_recursive();
-        commands
-            .spawn((LevelRoot, SpatialBundle::default()))
-            .with_children(|parent| {
-                level
-                    .map
-                    .axis_iter(Axis(0))
-                    .enumerate()
-                    .for_each(|(j, row)| {
-                        row.iter().enumerate().for_each(|(i, cell)| {
-                            let es = cell.construct(parent, Coord::new((j, i)), atlas.clone());
-                            entities.insert(Coord::new((j, i)), es);
-                        });
+    // Only the last level asked for matters, and building more than one in a
+    // frame would leave two players behind.
+    let Some(LevelEvent(current_level)) = level_events.iter().last() else {
+        return;
+    };
+    let (root, _) = root
+        .get_single()
+        .expect("Always have a root outside of this system");
+    let Some(levels) = levels.get(&pack.0) else {
+        log::info!("The pack has not loaded yet");
+        return;
+    };
+    let Some(level) = levels.levels.get(*current_level) else {
+        log::info!(
+            "There is no level {}, the pack only has {}",
+            *current_level,
+            levels.levels.len()
+        );
+        return;
+    };
+    let mut level = level.clone();
+    level.player_pos = level.start_pos;
+    let mut window = window.single_mut();
+    window.title = format!("Level: {}, by {}", level.name, level.author);
+    let (height, width) = level.map.dim();
+
+    log::info!("Changing level: {}", *current_level);
+    log::info!("Width x Height: {width} x {height}");
+    log::info!("Player start: {:?}", level.start_pos);
+
+    let mut entities = HashMap::default();
+    let atlas = texture_data.atlas.clone();
+    commands.insert_resource(CurrentLevel(*current_level));
+    commands.insert_resource(LevelMap(level.clone()));
+    commands.insert_resource(Inventory::default());
+    commands.insert_resource(Score::default());
+    commands.entity(root).despawn_recursive();
+    commands
+        .spawn((LevelRoot, SpatialBundle::default()))
+        .with_children(|parent| {
+            level
+                .map
+                .axis_iter(Axis(0))
+                .enumerate()
+                .for_each(|(j, row)| {
+                    row.iter().enumerate().for_each(|(i, cell)| {
+                        let es = cell.construct(parent, Coord::new((j, i)), atlas.clone());
+                        entities.insert(Coord::new((j, i)), es);
                     });
-                parent.spawn((
-                    SpriteSheetBundle {
-                        transform: Transform::from_xyz(
-                            level.player_pos.1 as f32 * CELL_WIDTH,
-                            (*level.player_pos).0 as f32 * -CELL_WIDTH,
-                            1.0,
-                        ),
-                        texture_atlas: atlas.clone(),
-                        sprite: TextureAtlasSprite::new(0),
-                        ..Default::default()
-                    },
-                    Player,
-                ));
-            });
+                });
+            parent.spawn((
+                SpriteSheetBundle {
+                    transform: Transform::from_xyz(
+                        level.player_pos.1 as f32 * CELL_WIDTH,
+                        (*level.player_pos).0 as f32 * -CELL_WIDTH,
+                        1.0,
+                    ),
+                    texture_atlas: atlas.clone(),
+                    sprite: TextureAtlasSprite::new(0),
+                    ..Default::default()
+                },
+                Player,
+            ));
+        });
 
-        commands.insert_resource(LevelEntities(entities));
-    }
+    commands.insert_resource(LevelEntities(entities));
 }
 
-fn move_player(
+pub(crate) fn move_player(
     mut commands: Commands,
     mut movements: EventReader<MovementEvent>,
     mut level_map: ResMut<LevelMap>,
     mut level_entities: ResMut<LevelEntities>,
     mut inventory: ResMut<Inventory>,
+    mut score: ResMut<Score>,
+    mut history: ResMut<History>,
     mut transform: Query<&mut Transform, With<Player>>,
     root: Query<Entity, With<LevelRoot>>,
     texture_data: Res<TextureData>,
     mut death_events: EventWriter<DeathEvent>,
-    mut _sound_events: EventWriter<SoundEvent>,
-    mut level_events: EventWriter<LevelEvent>,
+    mut sound_events: EventWriter<SoundEvent>,
+    mut complete_events: EventWriter<CompleteEvent>,
 ) {
-    for dest in &mut movements {
-        let dest = dest.pos;
+    for movement in &mut movements {
         let root = root.single();
         let atlas = texture_data.atlas.clone();
-        let cell = level_map.map[*dest];
-        let delta = (
-            dest.0 .0 as isize - level_map.player_pos.0 .0 as isize,
-            dest.0 .1 as isize - level_map.player_pos.0 .1 as isize,
-        );
-        match cell.action() {
-            CellAction::Nothing => {}
-            CellAction::Consume {
-                item,
-                fail,
-                success,
-            } => {
-                if let ControlFlow::Break(_) = handle_consume(
-                    &mut inventory,
-                    &mut death_events,
-                    &mut level_map,
-                    &mut level_entities,
-                    &mut commands,
-                    root,
-                    atlas,
-                    dest,
-                    item,
-                    fail,
-                    success,
-                ) {
-                    return;
-                }
-            }
-            CellAction::Add(item, amount) => {
-                handle_add(
-                    &mut level_map,
-                    &mut level_entities,
-                    &mut inventory,
-                    &mut commands,
-                    root,
-                    atlas,
-                    dest,
-                    item,
-                    amount,
-                );
-            }
-            CellAction::Block => {
-                return;
-            }
-            CellAction::Explode => {
-                handle_explode(
-                    &mut level_map,
-                    &mut level_entities,
-                    &mut death_events,
-                    &mut commands,
-                    root,
-                    atlas,
-                    dest,
-                );
-                // TODO: Add animations
-            }
-            CellAction::Shoot => {
-                handle_shoot(
-                    &mut level_map,
-                    &mut level_entities,
-                    &mut death_events,
-                    &mut commands,
-                    root,
-                    atlas,
-                    dest,
-                    delta,
-                );
-            }
-            CellAction::Push => {
-                if let ControlFlow::Break(_) = handle_push(
-                    &mut level_map,
-                    &mut level_entities,
-                    &mut commands,
-                    root,
-                    atlas,
-                    dest,
-                    delta,
-                ) {
-                    return;
-                }
-            }
-            CellAction::Teleport(_, _) => todo!(),
-            CellAction::Die(msg) => {
-                death_events.send(DeathEvent(msg.to_string()));
-            }
-            CellAction::NextLevel => {
-                level_events.send(LevelEvent(level_map.number + 1));
-            }
+        let before = (level_map.0.clone(), inventory.clone());
+        let outcome = level_map.step(movement.dir, &mut inventory);
+        if outcome.moved || !outcome.changed.is_empty() {
+            history.record(before.0, before.1, *score);
+            score.count(&outcome);
         }
 
-        *level_map.player_pos = *dest;
-        let mut transform = transform.single_mut();
-        *transform = Transform::from_xyz(
-            level_map.player_pos.0 .1 as f32 * CELL_WIDTH,
-            level_map.player_pos.0 .0 as f32 * -CELL_WIDTH,
-            1.0,
-        );
-    }
-}
-
-fn handle_consume(
-    inventory: &mut Inventory,
-    death_events: &mut EventWriter<DeathEvent>,
-    level_map: &mut LevelMap,
-    level_entities: &mut LevelEntities,
-    commands: &mut Commands,
-    root: Entity,
-    atlas: Handle<TextureAtlas>,
-    dest: Coord,
-    item: Item,
-    fail: Box<CellAction>,
-    success: Box<CellAction>,
-) -> ControlFlow<()> {
-    let count = inventory.map.entry(item).or_insert(0);
-    // TODO: Make the inventory consumtion work
-    if *count == 0 {
-        match *fail {
-            CellAction::Block => {
-                // *dest = *level_map.player_pos;
-                return ControlFlow::Break(());
-            }
-            CellAction::Die(msg) => {
-                death_events.send(DeathEvent(msg.to_string()));
-                return ControlFlow::Break(());
-            }
-            _ => panic!("unhandled failure"),
+        for (coord, cell) in outcome.changed {
+            replace_tile(
+                &mut commands,
+                &mut level_entities,
+                root,
+                atlas.clone(),
+                coord,
+                cell,
+            );
         }
-    } else {
-        *count -= 1;
-        // TODO: Delete object with effect, and perform action.
-        let old = &mut level_map.map[*dest];
-        // TODO: Handle water texture
-        if old != &Cell::Water {
-            *old = Cell::Empty;
-            let entity = level_entities
-                .get_mut(&dest)
-                .expect("should have all positions");
-            commands.entity(entity.clone()).despawn_recursive();
+        for coord in outcome.explosions {
             commands.entity(root).with_children(|parent| {
-                *entity = Cell::Empty.construct(parent, dest, atlas);
+                let explosion = Explosion::default();
+                let index = EXPLOSION_INDICES[explosion.texture_index];
+                parent.spawn((
+                    explosion,
+                    SpriteSheetBundle {
+                        transform: Transform::from_xyz(
+                            coord.0 .1 as f32 * CELL_WIDTH,
+                            coord.0 .0 as f32 * -CELL_WIDTH,
+                            0.5,
+                        ),
+                        texture_atlas: atlas.clone(),
+                        sprite: TextureAtlasSprite::new(index),
+                        ..Default::default()
+                    },
+                ));
             });
         }
-        match *success {
-            CellAction::Nothing => {}
-            CellAction::Block => {
-                // TODO: Make this explode
-                // *dest = *level_map.player_pos;
-                return ControlFlow::Break(());
-            }
-            _ => panic!("unhandled success"),
+        for sound in outcome.sounds {
+            sound_events.send(SoundEvent { sound });
+        }
+        if let Some(msg) = outcome.death {
+            death_events.send(DeathEvent(msg.to_string()));
+        }
+        if outcome.complete && outcome.death.is_none() {
+            complete_events.send(CompleteEvent);
+        }
+
+        if outcome.moved {
+            let mut transform = transform.single_mut();
+            *transform = Transform::from_xyz(
+                level_map.player_pos.0 .1 as f32 * CELL_WIDTH,
+                level_map.player_pos.0 .0 as f32 * -CELL_WIDTH,
+                1.0,
+            );
         }
     }
-    ControlFlow::Continue(())
 }
 
-fn handle_add(
-    level_map: &mut LevelMap,
-    level_entities: &mut LevelEntities,
-    inventory: &mut Inventory,
+pub(crate) fn replace_tile(
     commands: &mut Commands,
+    level_entities: &mut LevelEntities,
     root: Entity,
     atlas: Handle<TextureAtlas>,
-    dest: Coord,
-    item: Item,
-    amount: usize,
+    coord: Coord,
+    cell: Cell,
 ) {
-    level_map.map[*dest] = Cell::Empty;
-    *inventory.map.entry(item).or_insert(0) += amount;
     let entity = level_entities
-        .get_mut(&dest)
+        .get_mut(&coord)
         .expect("should have all positions");
-    commands.entity(entity.clone()).despawn_recursive();
+    commands.entity(*entity).despawn_recursive();
     commands.entity(root).with_children(|parent| {
-        *entity = Cell::Empty.construct(parent, dest, atlas);
+        *entity = cell.construct(parent, coord, atlas);
     });
 }
 
-fn handle_explode(
-    level_map: &mut LevelMap,
-    level_entities: &mut LevelEntities,
-    death_events: &mut EventWriter<DeathEvent>,
-    commands: &mut Commands,
-    root: Entity,
-    atlas: Handle<TextureAtlas>,
-    dest: Coord,
-) {
-    let explode_cells = level_map.explode_cells(dest);
-    explode_cells.iter().for_each(|dest| {
-        let old = &mut level_map.map[**dest];
-        if old == &Cell::Barrel {
-            death_events.send(DeathEvent("You died in an explosion".to_string()));
-            return;
-        }
-        if old == &Cell::Exit {
-            death_events.send(DeathEvent("You blew up the exit".to_string()));
-            return;
-        }
-        if old == &Cell::Water {
-            // Water doesn't get destroyed in explosions
-            return;
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::events::Sound;
+
+    /// Builds a level from rows in the .dat cell notation.
+    pub(crate) fn level(rows: &[&str]) -> Level {
+        RawLevel {
+            rows: rows.iter().map(|row| parse_row(row).unwrap()).collect(),
+            ..Default::default()
         }
-        *old = Cell::Empty;
-        let entity = level_entities
-            .get_mut(&dest)
-            .expect("should have all positions");
-        commands.entity(entity.clone()).despawn_recursive();
-        commands.entity(root).with_children(|parent| {
-            *entity = Cell::Empty.construct(parent, *dest, atlas.clone());
-            let explosion = Explosion::default();
-            let index = EXPLOSION_INDICES[explosion.texture_index];
-            parent.spawn((
-                explosion,
-                SpriteSheetBundle {
-                    transform: Transform::from_xyz(
-                        dest.0 .1 as f32 * CELL_WIDTH,
-                        dest.0 .0 as f32 * -CELL_WIDTH,
-                        0.5,
-                    ),
-                    texture_atlas: atlas.clone(),
-                    sprite: TextureAtlasSprite::new(index),
-                    ..Default::default()
-                },
-            ));
-        });
-    })
-}
+        .into_level(0)
+        .unwrap()
+    }
 
-fn handle_shoot(
-    level_map: &mut LevelMap,
-    level_entities: &mut LevelEntities,
-    death_events: &mut EventWriter<DeathEvent>,
-    commands: &mut Commands,
-    root: Entity,
-    atlas: Handle<TextureAtlas>,
-    dest: Coord,
-    delta: (isize, isize),
-) {
-    let old = &mut level_map.map[*dest];
-    if old == &Cell::Barrel {
-        death_events.send(DeathEvent("You died in an explosion".to_string()));
-        return;
+    pub(crate) fn at(row: usize, column: usize) -> Coord {
+        Coord::new((row, column))
     }
-    if old == &Cell::Exit {
-        death_events.send(DeathEvent("You blew up the exit".to_string()));
-        return;
+
+    #[test]
+    fn teleports_out_of_the_partner_in_its_direction() {
+        // The partner points down, out of the second row.
+        let mut level = level(&["st14 t12", "     "]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(outcome.moved);
+        assert_eq!(outcome.sounds, vec![Sound::Teleport]);
+        assert_eq!(level.player_pos, at(1, 3));
     }
-    if old == &Cell::Water {
-        return;
+
+    #[test]
+    fn teleporters_form_a_ring_in_reading_order() {
+        let mut level = level(&["st14 t14 t14 "]);
+        assert_eq!(level.teleport_partner(at(0, 1), 1), Some(at(0, 3)));
+        assert_eq!(level.teleport_partner(at(0, 3), 1), Some(at(0, 5)));
+        assert_eq!(level.teleport_partner(at(0, 5), 1), Some(at(0, 1)));
+        level.step(Dir::Right, &mut Inventory::default());
+        assert_eq!(level.player_pos, at(0, 4));
     }
-    *old = Cell::Empty;
-    let entity = level_entities
-        .get_mut(&dest)
-        .expect("should have all positions");
-    commands.entity(entity.clone()).despawn_recursive();
-    commands.entity(root).with_children(|parent| {
-        *entity = Cell::Empty.construct(parent, dest, atlas.clone());
-    });
-    let neighbor = level_map.neighbor(dest, delta);
-    if let Some(neighbor) = neighbor {
-        level_map.map[*neighbor] = Cell::Empty;
-        let entity = level_entities
-            .get_mut(&neighbor)
-            .expect("should have all positions");
-        commands.entity(entity.clone()).despawn_recursive();
-        commands.entity(root).with_children(|parent| {
-            *entity = Cell::Empty.construct(parent, neighbor, atlas.clone());
-            let explosion = Explosion::default();
-            let index = EXPLOSION_INDICES[explosion.texture_index];
-            parent.spawn((
-                explosion,
-                SpriteSheetBundle {
-                    transform: Transform::from_xyz(
-                        neighbor.0 .1 as f32 * CELL_WIDTH,
-                        neighbor.0 .0 as f32 * -CELL_WIDTH,
-                        10.0,
-                    ),
-                    texture_atlas: atlas.clone(),
-                    sprite: TextureAtlasSprite::new(index),
-                    ..Default::default()
-                },
-            ));
-        });
+
+    #[test]
+    fn an_exit_off_the_map_blocks() {
+        let mut level = level(&["st14 t14"]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(!outcome.moved);
+        assert_eq!(outcome.sounds, vec![Sound::HitWall]);
+        assert_eq!(level.player_pos, at(0, 0));
     }
-}
 
-fn handle_push(
-    level_map: &mut LevelMap,
-    level_entities: &mut LevelEntities,
-    commands: &mut Commands,
-    root: Entity,
-    atlas: Handle<TextureAtlas>,
-    dest: Coord,
-    delta: (isize, isize),
-) -> ControlFlow<()> {
-    let neighbor = level_map.neighbor(dest, delta);
-    if let Some(neighbor) = neighbor {
-        let cell = level_map.map[*neighbor];
-        if cell != Cell::Empty && cell != Cell::Start {
-            // *dest.0 = *level_map.player_pos;
-            return ControlFlow::Break(());
-        }
-        level_map.map[*dest] = Cell::Empty;
-        let entity = level_entities
-            .get_mut(&dest)
-            .expect("should have all positions");
-        commands.entity(entity.clone()).despawn_recursive();
-        commands.entity(root).with_children(|parent| {
-            *entity = Cell::Empty.construct(parent, dest, atlas.clone());
-        });
-        level_map.map[*neighbor] = Cell::JellyBean;
-        let entity = level_entities
-            .get_mut(&neighbor)
-            .expect("should have all positions");
-        commands.entity(entity.clone()).despawn_recursive();
-        commands.entity(root).with_children(|parent| {
-            *entity = Cell::JellyBean.construct(parent, neighbor, atlas.clone());
-        });
+    #[test]
+    fn a_teleporter_loop_blocks() {
+        // Each teleporter sends the player straight back into the other.
+        let mut level = level(&["st14t13"]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(!outcome.moved);
+        assert_eq!(level.player_pos, at(0, 0));
+    }
+
+    #[test]
+    fn a_lone_teleporter_is_floor() {
+        let mut level = level(&["st14"]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(outcome.moved);
+        assert_eq!(level.player_pos, at(0, 1));
+    }
+
+    #[test]
+    fn resolves_nested_consumes() {
+        let action = CellAction::Consume {
+            item: Item::Key,
+            fail: Box::new(CellAction::Block),
+            success: Box::new(CellAction::Consume {
+                item: Item::Key,
+                fail: Box::new(CellAction::Die("one key short")),
+                success: Box::new(CellAction::Nothing),
+            }),
+        };
+        let mut inventory = Inventory::default();
+        assert_eq!(action.clone().resolve(&inventory), CellAction::Block);
+
+        inventory.add(Item::Key, 1);
+        assert_eq!(
+            action.clone().resolve(&inventory),
+            CellAction::Consume {
+                item: Item::Key,
+                fail: Box::new(CellAction::Block),
+                success: Box::new(CellAction::Die("one key short")),
+            }
+        );
+
+        inventory.add(Item::Key, 1);
+        let CellAction::Consume { success, .. } = action.resolve(&inventory) else {
+            panic!("two keys pass the first lock");
+        };
+        assert!(matches!(
+            *success,
+            CellAction::Consume { success, .. } if *success == CellAction::Nothing
+        ));
+    }
+
+    #[test]
+    fn fingerprints_ignore_the_format_and_header() {
+        let bytes =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/levels.dat")).unwrap();
+        let dat = PackFormat::Dat.parse(&bytes).unwrap();
+        let mut ron = PackFormat::Ron.parse(&PackFormat::Ron.write(&dat)).unwrap();
+        ron.checksum = 0;
+        assert_eq!(dat.fingerprint(), ron.fingerprint());
+
+        ron.levels[0].name.push_str(" (edited)");
+        assert_ne!(dat.fingerprint(), ron.fingerprint());
     }
-    ControlFlow::Continue(())
 }
@@ -1,107 +1,280 @@
-use std::{iter::repeat, sync::atomic::AtomicUsize};
+use std::{fmt, iter::repeat};
 
 use ndarray::Array2;
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{line_ending, not_line_ending, one_of, u32},
-    combinator::map,
-    error::{ParseError, VerboseError},
+    combinator::{eof, map},
+    error::ParseError,
     multi::many1,
     sequence::{terminated, tuple},
     Finish, IResult,
 };
 
-use super::{Cell, Dir, Level, Levels, Coord};
-
-static LEVEL_NUMBER: AtomicUsize = AtomicUsize::new(0);
+use super::{Cell, Coord, Dir, Level, LevelInfo, Levels};
 
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum LevelLoadError {
+pub enum LevelLoadErrorKind {
     BadFormat,
     BadChecksum,
     BadHeader,
     InvalidCharacter,
+    BadTeleportId,
+    BadTeleportDir,
+    MissingStart,
+    NoLevels,
+}
+
+/// What went wrong loading a pack, and as much as is known about where.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelLoadError {
+    pub kind: LevelLoadErrorKind,
+    /// Index of the level in the pack.
+    pub level: Option<usize>,
+    pub name: Option<String>,
+    /// Line and column, counting from 1. In structured packs the line is the
+    /// row within the level.
+    pub position: Option<(usize, usize)>,
+    /// The character the parser stopped at, `None` at the end of the file.
+    pub found: Option<char>,
+    /// How much input was left when the parser stopped, to find `position`.
+    remaining: Option<usize>,
+}
+
+impl LevelLoadError {
+    fn new(kind: LevelLoadErrorKind) -> Self {
+        Self {
+            kind,
+            level: None,
+            name: None,
+            position: None,
+            found: None,
+            remaining: None,
+        }
+    }
+
+    pub(crate) fn in_level(mut self, level: usize) -> Self {
+        self.level.get_or_insert(level);
+        self
+    }
+
+    pub(crate) fn named(mut self, name: &str) -> Self {
+        self.name.get_or_insert_with(|| name.to_string());
+        self
+    }
+
+    fn locate(mut self, input: &[u8]) -> Self {
+        if let Some(remaining) = self.remaining {
+            let read = &input[..input.len() - remaining];
+            let line_start = read.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+            let line = read.iter().filter(|b| **b == b'\n').count() + 1;
+            self.position = Some((line, read.len() - line_start + 1));
+        }
+        self
+    }
 }
 
-impl<I> ParseError<I> for LevelLoadError {
-    fn from_error_kind(_input: I, _kind: nom::error::ErrorKind) -> Self {
-        Self::BadFormat
+impl fmt::Display for LevelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.level, &self.name) {
+            (Some(level), Some(name)) => write!(f, "level {level} ({name:?}), ")?,
+            (Some(level), None) => write!(f, "level {level}, ")?,
+            (None, Some(name)) => write!(f, "level {name:?}, ")?,
+            (None, None) => {}
+        }
+        if let Some((line, column)) = self.position {
+            write!(f, "line {line}, column {column}: ")?;
+        }
+        f.write_str(match self.kind {
+            LevelLoadErrorKind::BadFormat => "unexpected",
+            LevelLoadErrorKind::BadChecksum => "the checksum does not match the levels",
+            LevelLoadErrorKind::BadHeader => "expected \"Mushroom Man 3.0\", found",
+            LevelLoadErrorKind::InvalidCharacter => "unknown cell",
+            LevelLoadErrorKind::BadTeleportId => "bad teleport id",
+            LevelLoadErrorKind::BadTeleportDir => "bad teleport direction",
+            LevelLoadErrorKind::MissingStart => "no start",
+            LevelLoadErrorKind::NoLevels => "no levels",
+        })?;
+        match (self.position, self.found) {
+            (Some(_), Some(c)) => write!(f, " {c:?}"),
+            (Some(_), None) => write!(f, " end of file"),
+            (None, _) => Ok(()),
+        }
     }
+}
 
-    fn append(_input: I, _kind: nom::error::ErrorKind, other: Self) -> Self {
+impl std::error::Error for LevelLoadError {}
+
+impl<'a> ParseError<&'a [u8]> for LevelLoadError {
+    fn from_error_kind(input: &'a [u8], _kind: nom::error::ErrorKind) -> Self {
+        Self {
+            found: input.first().map(|b| *b as char),
+            remaining: Some(input.len()),
+            ..Self::new(LevelLoadErrorKind::BadFormat)
+        }
+    }
+
+    fn append(_input: &'a [u8], _kind: nom::error::ErrorKind, other: Self) -> Self {
         other
     }
 }
 
+/// Stops parsing with `kind` where a parser failed, unless a parser further in
+/// already knew better what went wrong.
+fn fail(kind: LevelLoadErrorKind) -> impl Fn(nom::Err<LevelLoadError>) -> nom::Err<LevelLoadError> {
+    move |e| match e {
+        nom::Err::Error(e) => nom::Err::Failure(LevelLoadError { kind, ..e }),
+        e => e,
+    }
+}
+
+/// A level exactly as it is written in a pack, before it is checked and
+/// turned into a [`Level`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RawLevel {
+    pub(crate) name: String,
+    pub(crate) author: String,
+    pub(crate) rows: Vec<Vec<Cell>>,
+    pub(crate) info: LevelInfo,
+}
+
+impl RawLevel {
+    /// Builds the level that sits at index `number` in its pack.
+    pub(crate) fn into_level(self, number: usize) -> Result<Level, LevelLoadError> {
+        let row_widths: Vec<_> = self.rows.iter().map(Vec::len).collect();
+        let width = row_widths.iter().copied().max().unwrap_or(0);
+        let height = self.rows.len();
+        let map = Array2::from_shape_vec(
+            (height, width),
+            self.rows
+                .into_iter()
+                .flat_map(|row| {
+                    let padding = width - row.len();
+                    row.into_iter().chain(repeat(Cell::Empty).take(padding))
+                })
+                .collect(),
+        )
+        .expect("rows are padded to the same width");
+        let player_pos = Coord::new(
+            map.indexed_iter()
+                .find(|(_p, c)| c == &&Cell::Start)
+                .ok_or_else(|| {
+                    LevelLoadError::new(LevelLoadErrorKind::MissingStart).named(&self.name)
+                })?
+                .0,
+        );
+
+        Ok(Level {
+            name: self.name,
+            author: self.author,
+            number,
+            map,
+            row_widths,
+            start_pos: player_pos,
+            player_pos,
+            info: self.info,
+        })
+    }
+}
+
 pub(crate) fn parse_levels(input: &[u8]) -> Result<Levels, LevelLoadError> {
-    levels(input).finish().map(|(_, levels)| levels)
-}
-
-fn levels(input: &[u8]) -> IResult<&[u8], Levels, LevelLoadError> {
-    let (input, _) = tuple((tag(b"Mushroom Man 3.0"), line_ending::<_, VerboseError<_>>))(input)
-        .map_err(|_| nom::Err::Error(LevelLoadError::BadHeader))?;
-    let (input, checksum) = terminated(u32, line_ending::<_, VerboseError<_>>)(input)
-        .map_err(|_| nom::Err::Error(LevelLoadError::BadFormat))?;
-    let (input, _) = line_ending::<_, VerboseError<_>>(input)
-        .map_err(|_| nom::Err::Error(LevelLoadError::BadFormat))?;
-    let (input, levels) = many1::<_, _, LevelLoadError, _>(level)(input)?;
-    // TODO: Calculate checksum and throw error if bad
-    assert!(levels.len() > 99);
+    let (checksum, levels) = parse_raw_levels(input)?;
+    let levels = levels
+        .into_iter()
+        .enumerate()
+        .map(|(n, level)| level.into_level(n).map_err(|e| e.in_level(n)))
+        .collect::<Result<_, _>>()?;
+    Ok(Levels { checksum, levels })
+}
+
+pub(crate) fn parse_raw_levels(input: &[u8]) -> Result<(u32, Vec<RawLevel>), LevelLoadError> {
+    levels(input)
+        .finish()
+        .map(|(_, levels)| levels)
+        .map_err(|e| e.locate(input))
+}
+
+/// Reads the header up to the end of the checksum line.
+fn header(input: &[u8]) -> IResult<&[u8], u32, LevelLoadError> {
+    let (input, _) = tuple((tag(b"Mushroom Man 3.0"), line_ending))(input)
+        .map_err(fail(LevelLoadErrorKind::BadHeader))?;
+    terminated(u32, line_ending)(input).map_err(fail(LevelLoadErrorKind::BadFormat))
+}
+
+fn levels(input: &[u8]) -> IResult<&[u8], (u32, Vec<RawLevel>), LevelLoadError> {
+    let (input, checksum) = header(input)?;
+    let (mut input, _) = line_ending(input).map_err(fail(LevelLoadErrorKind::BadFormat))?;
+    let mut levels = vec![];
+    while !input.iter().all(u8::is_ascii_whitespace) {
+        let (rest, level) = level(input).map_err(|e| e.map(|e| e.in_level(levels.len())))?;
+        levels.push(level);
+        input = rest;
+    }
+    if levels.is_empty() {
+        return Err(nom::Err::Failure(LevelLoadError::new(
+            LevelLoadErrorKind::NoLevels,
+        )));
+    }
+    // TODO: Verify the checksum and return BadChecksum when it is wrong. The
+    // original algorithm is unknown: the value in the bundled levels.dat does
+    // not match byte sums, position-weighted sums (per file, level, row or
+    // column), CRC32, Adler-32 or simple rolling hashes of its contents, so
+    // for now it is only read.
     log::info!("Found {} levels", levels.len());
 
-    Ok((input, Levels { checksum, levels }))
-}
-
-fn level(input: &[u8]) -> IResult<&[u8], Level, LevelLoadError> {
-    let (input, name) = map(
-        terminated(not_line_ending, line_ending::<_, VerboseError<_>>),
-        String::from_utf8_lossy,
-    )(input)
-    .map_err(|_| nom::Err::Error(LevelLoadError::BadFormat))?;
-    let (input, author) = map(
-        terminated(not_line_ending, line_ending::<_, VerboseError<_>>),
-        String::from_utf8_lossy,
-    )(input)
-    .map_err(|_| nom::Err::Error(LevelLoadError::BadFormat))?;
-    let (input, mut rows): (_, Vec<Vec<Cell>>) = many1(row)(input)?;
-    let (input, _) = line_ending::<_, VerboseError<&[u8]>>(input)
-        .map_err(|_| nom::Err::Error(LevelLoadError::BadFormat))?;
-
-    let width = rows[0].len();
-    rows.iter_mut().for_each(|row| {
-        if row.len() < width {
-            row.extend(repeat(Cell::Empty).take(width - row.len()));
-        }
-    });
-    let map = Array2::from_shape_vec(
-        (rows.len(), rows[0].len()),
-        rows.into_iter().flatten().collect(),
-    )
-    .unwrap();
-    let player_pos = Coord::new(
-        map.indexed_iter()
-            .find(|(_p, c)| c == &&Cell::Start)
-            .expect("need a start")
-            .0,
-    );
+    Ok((input, (checksum, levels)))
+}
+
+fn level(input: &[u8]) -> IResult<&[u8], RawLevel, LevelLoadError> {
+    let (input, name) = text_line(input)?;
+    let named = |e: nom::Err<LevelLoadError>| e.map(|e| e.named(&name));
+    let (input, author) = text_line(input).map_err(named)?;
+    let (input, rows) = many1(row)(input)
+        .map_err(fail(LevelLoadErrorKind::InvalidCharacter))
+        .map_err(named)?;
+    let (input, _) = end_of_line(input)
+        .map_err(fail(LevelLoadErrorKind::InvalidCharacter))
+        .map_err(named)?;
 
     Ok((
         input,
-        Level {
-            name: name.to_string(),
-            author: author.to_string(),
-            number: LEVEL_NUMBER.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
-            map,
-            start_pos: player_pos,
-            player_pos,
+        RawLevel {
+            name,
+            author,
+            rows,
+            info: LevelInfo::default(),
         },
     ))
 }
 
+/// Reads a single row written the .dat way, as structured packs store them.
+pub(crate) fn parse_row(line: &str) -> Result<Vec<Cell>, LevelLoadError> {
+    let input = line.as_bytes();
+    terminated(many1(cell), eof)(input)
+        .map_err(fail(LevelLoadErrorKind::InvalidCharacter))
+        .finish()
+        .map(|(_, cells)| cells)
+        .map_err(|e| e.locate(input))
+}
+
+fn text_line(input: &[u8]) -> IResult<&[u8], String, LevelLoadError> {
+    // The original game wrote names in Latin-1.
+    map(terminated(not_line_ending, line_ending), |line: &[u8]| {
+        line.iter().map(|b| *b as char).collect()
+    })(input)
+    .map_err(fail(LevelLoadErrorKind::BadFormat))
+}
+
+fn end_of_line(input: &[u8]) -> IResult<&[u8], &[u8], LevelLoadError> {
+    alt((line_ending, eof))(input)
+}
+
 fn row(input: &[u8]) -> IResult<&[u8], Vec<Cell>, LevelLoadError> {
-    terminated(many1(cell), line_ending)(input)
+    let (input, cells) = many1(cell)(input)?;
+    let (input, _) = end_of_line(input).map_err(fail(LevelLoadErrorKind::InvalidCharacter))?;
+    Ok((input, cells))
 }
 
 fn cell(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
@@ -168,19 +341,16 @@ fn water(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
 }
 
 fn teleport(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
-    map(
-        tuple((
-            tag(b"t"),
-            one_of("12345"),
-            alt((
-                map(tag(b"1"), |_| Dir::Up),
-                map(tag(b"2"), |_| Dir::Down),
-                map(tag(b"3"), |_| Dir::Left),
-                map(tag(b"4"), |_| Dir::Right),
-            )),
-        )),
-        |(_, id, dir)| Cell::Teleport(id as u8 - b'0' as u8, dir),
-    )(input)
+    let (input, _) = tag(b"t")(input)?;
+    let (input, id) = one_of("12345")(input).map_err(fail(LevelLoadErrorKind::BadTeleportId))?;
+    let (input, dir) = alt((
+        map(tag(b"1"), |_| Dir::Up),
+        map(tag(b"2"), |_| Dir::Down),
+        map(tag(b"3"), |_| Dir::Left),
+        map(tag(b"4"), |_| Dir::Right),
+    ))(input)
+    .map_err(fail(LevelLoadErrorKind::BadTeleportDir))?;
+    Ok((input, Cell::Teleport(id as u8 - b'0', dir)))
 }
 
 fn oxygen(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
@@ -194,3 +364,73 @@ fn gun(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
 fn lock(input: &[u8]) -> IResult<&[u8], Cell, LevelLoadError> {
     map(tag(b"l"), |_| Cell::Lock)(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/levels.dat");
+
+    #[test]
+    fn levels_are_numbered_per_pack() {
+        let bytes = std::fs::read(BUNDLED).unwrap();
+        // A second load must not carry on counting from the first.
+        for _ in 0..2 {
+            let levels = parse_levels(&bytes).unwrap();
+            for (n, level) in levels.levels.iter().enumerate() {
+                assert_eq!(level.number, n);
+            }
+        }
+    }
+
+    fn error(second_row: &str) -> LevelLoadError {
+        let pack = format!(
+            "Mushroom Man 3.0\r\n0\r\n\r\nFirst\r\nMe\r\nse\r\n\r\nSecond\r\nMe\r\n{second_row}\r\n"
+        );
+        parse_levels(pack.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn reports_where_an_unknown_cell_is() {
+        let e = error("sxe");
+        assert_eq!(e.kind, LevelLoadErrorKind::InvalidCharacter);
+        assert_eq!(e.level, Some(1));
+        assert_eq!(e.name.as_deref(), Some("Second"));
+        assert_eq!(e.position, Some((10, 2)));
+        assert_eq!(e.found, Some('x'));
+        assert_eq!(
+            e.to_string(),
+            "level 1 (\"Second\"), line 10, column 2: unknown cell 'x'"
+        );
+    }
+
+    #[test]
+    fn reports_where_a_bad_teleport_direction_is() {
+        let e = error("st15e");
+        assert_eq!(e.kind, LevelLoadErrorKind::BadTeleportDir);
+        assert_eq!(e.level, Some(1));
+        assert_eq!(e.name.as_deref(), Some("Second"));
+        assert_eq!(e.position, Some((10, 4)));
+        assert_eq!(e.found, Some('5'));
+    }
+
+    #[test]
+    fn loads_packs_of_any_size() {
+        let levels = parse_levels(b"Mushroom Man 3.0\r\n0\r\n\r\nOnly\r\nMe\r\nse\r\n").unwrap();
+        assert_eq!(levels.levels.len(), 1);
+        assert_eq!(levels.levels[0].name, "Only");
+    }
+
+    #[test]
+    fn an_empty_pack_has_no_levels() {
+        for pack in [
+            &b"Mushroom Man 3.0\r\n0\r\n\r\n"[..],
+            b"Mushroom Man 3.0\r\n0\r\n\r\n\r\n",
+        ] {
+            assert_eq!(
+                parse_levels(pack).map(|_| ()).map_err(|e| e.kind),
+                Err(LevelLoadErrorKind::NoLevels)
+            );
+        }
+    }
+}
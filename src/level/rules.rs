@@ -0,0 +1,254 @@
+use super::{Cell, CellAction, Coord, Dir, Inventory, Item, Level};
+use crate::events::Sound;
+
+/// Everything that happened during a single [`Level::step`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// Whether the player ended up on a new square.
+    pub moved: bool,
+    /// Cells that changed, with their new contents, in the order they changed.
+    pub changed: Vec<(Coord, Cell)>,
+    /// Squares that should show an explosion.
+    pub explosions: Vec<Coord>,
+    /// Inventory changes, in the order they happened.
+    pub items: Vec<(Item, isize)>,
+    pub death: Option<&'static str>,
+    pub complete: bool,
+    /// Whether a jelly bean was pushed along.
+    pub pushed: bool,
+    /// Sounds to play, in the order they happened.
+    pub sounds: Vec<Sound>,
+}
+
+impl StepOutcome {
+    fn set(&mut self, level: &mut Level, c: Coord, cell: Cell) {
+        level.map[*c] = cell;
+        self.changed.push((c, cell));
+    }
+
+    fn die(&mut self, msg: &'static str) {
+        if self.death.is_none() {
+            self.sounds.push(Sound::PlayerDie);
+        }
+        self.death.get_or_insert(msg);
+    }
+}
+
+impl Level {
+    /// Tries to move the player one square in `dir`, applying whatever the
+    /// square they walk onto does to the map and `inventory`.
+    pub fn step(&mut self, dir: Dir, inventory: &mut Inventory) -> StepOutcome {
+        let mut outcome = StepOutcome::default();
+        let Some(mut dest) = self.neighbor(self.player_pos, dir.delta()) else {
+            outcome.sounds.push(Sound::HitWall);
+            return outcome;
+        };
+        let mut delta = dir.delta();
+
+        // Stepping onto a teleporter moves the player out of its partner in the
+        // partner's direction, as if they had walked onto that square instead.
+        // An exit off the map, or a ring of teleporters that never lets the
+        // player out, blocks the move.
+        let mut hops = 0;
+        while let Cell::Teleport(id, _) = self.map[*dest] {
+            let Some(partner) = self.teleport_partner(dest, id) else {
+                break;
+            };
+            let Cell::Teleport(_, dir) = self.map[*partner] else {
+                unreachable!("partners are always teleporters");
+            };
+            hops += 1;
+            if hops > self.map.len() {
+                outcome.sounds.push(Sound::HitWall);
+                return outcome;
+            }
+            let Some(exit) = self.neighbor(partner, dir.delta()) else {
+                outcome.sounds.push(Sound::HitWall);
+                return outcome;
+            };
+            dest = exit;
+            delta = dir.delta();
+        }
+
+        let action = self.map[*dest].action(inventory);
+        if self.apply(&mut outcome, inventory, dest, delta, action) {
+            if hops > 0 {
+                outcome.sounds.insert(0, Sound::Teleport);
+            }
+            self.player_pos = dest;
+            outcome.moved = true;
+        }
+        outcome
+    }
+
+    /// Applies `action` to the square at `dest`, returning whether the player
+    /// gets to stand on it afterwards.
+    fn apply(
+        &mut self,
+        outcome: &mut StepOutcome,
+        inventory: &mut Inventory,
+        dest: Coord,
+        delta: (isize, isize),
+        action: CellAction,
+    ) -> bool {
+        match action {
+            CellAction::Nothing => true,
+            CellAction::Consume { item, success, .. } => {
+                // Resolved against the inventory, so the item is there.
+                inventory.take(item);
+                outcome.items.push((item, -1));
+                // TODO: Handle water texture
+                if self.map[*dest] != Cell::Water {
+                    outcome.set(self, dest, Cell::Empty);
+                }
+                self.apply(outcome, inventory, dest, delta, *success)
+            }
+            CellAction::Add(item, amount) => {
+                outcome.set(self, dest, Cell::Empty);
+                inventory.add(item, amount);
+                outcome.items.push((item, amount as isize));
+                outcome.sounds.push(Sound::Pickup);
+                true
+            }
+            CellAction::Block => {
+                outcome.sounds.push(Sound::HitWall);
+                false
+            }
+            CellAction::Explode => {
+                self.explode(outcome, dest);
+                true
+            }
+            CellAction::Shoot => {
+                self.shoot(outcome, dest, delta);
+                true
+            }
+            CellAction::Push => self.push(outcome, dest, delta),
+            CellAction::Teleport(_, _) => {
+                // Only reached for a teleporter without a partner, which is
+                // just floor.
+                true
+            }
+            CellAction::Die(msg) => {
+                // The player dies at the edge rather than on the square.
+                outcome.die(msg);
+                false
+            }
+            CellAction::NextLevel => {
+                outcome.complete = true;
+                true
+            }
+        }
+    }
+
+    fn explode(&mut self, outcome: &mut StepOutcome, dest: Coord) {
+        outcome.sounds.push(Sound::Explosion);
+        for c in self.explode_cells(dest) {
+            match self.map[*c] {
+                Cell::Barrel => outcome.die("You died in an explosion"),
+                Cell::Exit => outcome.die("You blew up the exit"),
+                // Water doesn't get destroyed in explosions
+                Cell::Water => {}
+                _ => {
+                    outcome.set(self, c, Cell::Empty);
+                    outcome.explosions.push(c);
+                }
+            }
+        }
+    }
+
+    fn shoot(&mut self, outcome: &mut StepOutcome, dest: Coord, delta: (isize, isize)) {
+        outcome.sounds.push(Sound::Gunshot);
+        outcome.set(self, dest, Cell::Empty);
+        if let Some(target) = self.neighbor(dest, delta) {
+            outcome.set(self, target, Cell::Empty);
+            outcome.explosions.push(target);
+        }
+    }
+
+    fn push(&mut self, outcome: &mut StepOutcome, dest: Coord, delta: (isize, isize)) -> bool {
+        let Some(target) = self.neighbor(dest, delta) else {
+            outcome.sounds.push(Sound::HitWall);
+            return false;
+        };
+        let cell = self.map[*target];
+        if cell != Cell::Empty && cell != Cell::Start {
+            outcome.sounds.push(Sound::HitWall);
+            return false;
+        }
+        outcome.set(self, dest, Cell::Empty);
+        outcome.set(self, target, Cell::JellyBean);
+        outcome.pushed = true;
+        outcome.sounds.push(Sound::Push);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::tests::{at, level};
+
+    #[test]
+    fn walks_onto_empty_squares() {
+        let mut level = level(&["s "]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(outcome.moved);
+        assert_eq!(level.player_pos, at(0, 1));
+    }
+
+    #[test]
+    fn walls_and_the_edge_block() {
+        let mut level = level(&["sw"]);
+        for dir in [Dir::Right, Dir::Left] {
+            let outcome = level.step(dir, &mut Inventory::default());
+            assert!(!outcome.moved);
+            assert_eq!(outcome.sounds, vec![Sound::HitWall]);
+        }
+        assert_eq!(level.player_pos, at(0, 0));
+    }
+
+    #[test]
+    fn picks_up_items() {
+        let mut level = level(&["so"]);
+        let mut inventory = Inventory::default();
+        let outcome = level.step(Dir::Right, &mut inventory);
+        assert!(outcome.moved);
+        assert_eq!(outcome.changed, vec![(at(0, 1), Cell::Empty)]);
+        assert_eq!(outcome.items, vec![(Item::Oxygen, 3)]);
+        assert_eq!(inventory.count(Item::Oxygen), 3);
+    }
+
+    #[test]
+    fn pushes_jelly_beans_into_empty_squares_only() {
+        let mut level = level(&["sj ", "sjw"]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(outcome.moved && outcome.pushed);
+        assert_eq!(level.map[(0, 2)], Cell::JellyBean);
+
+        level.player_pos = at(1, 0);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert!(!outcome.moved && !outcome.pushed);
+        assert_eq!(level.map[(1, 1)], Cell::JellyBean);
+    }
+
+    #[test]
+    fn dying_leaves_the_player_at_the_edge() {
+        let mut level = level(&["sh"]);
+        let outcome = level.step(Dir::Right, &mut Inventory::default());
+        assert_eq!(outcome.death, Some("You fell in a hole!"));
+        assert!(!outcome.moved);
+        assert_eq!(level.player_pos, at(0, 0));
+    }
+
+    #[test]
+    fn fills_holes_with_cement() {
+        let mut level = level(&["sh"]);
+        let mut inventory = Inventory::default();
+        inventory.add(Item::Cement, 1);
+        let outcome = level.step(Dir::Right, &mut inventory);
+        assert!(outcome.moved);
+        assert_eq!(outcome.death, None);
+        assert_eq!(outcome.items, vec![(Item::Cement, -1)]);
+        assert_eq!(level.map[(0, 1)], Cell::Empty);
+    }
+}
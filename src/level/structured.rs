@@ -0,0 +1,215 @@
+use std::{fmt, path::Path};
+
+use bevy::asset::{AssetLoader, LoadedAsset};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    parse::{parse_levels, parse_raw_levels, parse_row, LevelLoadError},
+    write::write_levels,
+    Level, LevelInfo, Levels, RawLevel,
+};
+
+/// A level pack written as RON or JSON instead of the original .dat format.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackFile {
+    /// Kept so a pack converted from .dat can be written back unchanged.
+    #[serde(default)]
+    pub checksum: u32,
+    pub levels: Vec<LevelFile>,
+}
+
+/// One level of a [`PackFile`]. Rows use the same letters as a .dat pack, so
+/// they stay readable and can be copied between the two.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub name: String,
+    pub author: String,
+    #[serde(default)]
+    pub difficulty: Option<u8>,
+    /// Number of moves a good solution takes.
+    #[serde(default)]
+    pub par: Option<usize>,
+    #[serde(default)]
+    pub hints: Vec<String>,
+    pub rows: Vec<String>,
+}
+
+impl LevelFile {
+    fn into_raw(self) -> Result<RawLevel, LevelLoadError> {
+        let rows = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(j, row)| {
+                parse_row(row).map_err(|mut e| {
+                    e.position = e.position.map(|(_, column)| (j + 1, column));
+                    e.named(&self.name)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RawLevel {
+            name: self.name,
+            author: self.author,
+            rows,
+            info: LevelInfo {
+                difficulty: self.difficulty,
+                par: self.par,
+                hints: self.hints,
+            },
+        })
+    }
+}
+
+impl From<&Level> for LevelFile {
+    fn from(level: &Level) -> Self {
+        Self {
+            name: level.name.clone(),
+            author: level.author.clone(),
+            difficulty: level.info.difficulty,
+            par: level.info.par,
+            hints: level.info.hints.clone(),
+            rows: level
+                .written_rows()
+                .map(|row| row.iter().map(ToString::to_string).collect())
+                .collect(),
+        }
+    }
+}
+
+impl PackFile {
+    fn into_raw(self) -> Result<(u32, Vec<RawLevel>), LevelLoadError> {
+        let levels = self
+            .levels
+            .into_iter()
+            .enumerate()
+            .map(|(n, level)| level.into_raw().map_err(|e| e.in_level(n)))
+            .collect::<Result<_, _>>()?;
+        Ok((self.checksum, levels))
+    }
+}
+
+impl From<&Levels> for PackFile {
+    fn from(levels: &Levels) -> Self {
+        Self {
+            checksum: levels.checksum,
+            levels: levels.levels.iter().map(LevelFile::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    Level(LevelLoadError),
+    Ron(ron::error::SpannedError),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::Level(e) => e.fmt(f),
+            PackError::Ron(e) => e.fmt(f),
+            PackError::Json(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PackError {}
+
+impl From<LevelLoadError> for PackError {
+    fn from(e: LevelLoadError) -> Self {
+        PackError::Level(e)
+    }
+}
+
+/// The ways a level pack can be written down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PackFormat {
+    Dat,
+    Ron,
+    Json,
+}
+
+impl PackFormat {
+    /// Picks the format from the file extension: `.dat`, `.pack.ron` or
+    /// `.pack.json`, the same ones the asset loaders are registered for.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        [
+            (".dat", PackFormat::Dat),
+            (".pack.ron", PackFormat::Ron),
+            (".pack.json", PackFormat::Json),
+        ]
+        .into_iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map(|(_, format)| format)
+    }
+
+    /// Reads the levels as written, before they are checked and numbered.
+    pub(crate) fn parse_raw(&self, bytes: &[u8]) -> Result<(u32, Vec<RawLevel>), PackError> {
+        let pack: PackFile = match self {
+            PackFormat::Dat => return Ok(parse_raw_levels(bytes)?),
+            PackFormat::Ron => ron::de::from_bytes(bytes).map_err(PackError::Ron)?,
+            PackFormat::Json => serde_json::from_slice(bytes).map_err(PackError::Json)?,
+        };
+        Ok(pack.into_raw()?)
+    }
+
+    pub fn parse(&self, bytes: &[u8]) -> Result<Levels, PackError> {
+        if *self == PackFormat::Dat {
+            return Ok(parse_levels(bytes)?);
+        }
+        let (checksum, levels) = self.parse_raw(bytes)?;
+        let levels = levels
+            .into_iter()
+            .enumerate()
+            .map(|(n, level)| level.into_level(n).map_err(|e| e.in_level(n)))
+            .collect::<Result<_, _>>()?;
+        Ok(Levels { checksum, levels })
+    }
+
+    /// Writes `levels` out in this format. Difficulty, par and hints have
+    /// nowhere to go in a .dat pack and are dropped.
+    pub fn write(&self, levels: &Levels) -> Vec<u8> {
+        match self {
+            PackFormat::Dat => write_levels(levels),
+            PackFormat::Ron => {
+                ron::ser::to_string_pretty(&PackFile::from(levels), Default::default())
+                    .expect("packs always serialize")
+                    .into_bytes()
+            }
+            PackFormat::Json => {
+                serde_json::to_vec_pretty(&PackFile::from(levels)).expect("packs always serialize")
+            }
+        }
+    }
+}
+
+/// Loads `.pack.ron` and `.pack.json` files, alongside [`LevelsLoader`] for
+/// `.dat`.
+///
+/// [`LevelsLoader`]: super::LevelsLoader
+#[derive(Debug, Clone, Default, Copy)]
+pub struct StructuredLevelsLoader;
+
+impl AssetLoader for StructuredLevelsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let format = PackFormat::from_path(load_context.path())
+                .expect("only registered for .pack.ron and .pack.json");
+            let levels = format
+                .parse(bytes)
+                .map_err(|e| bevy::asset::Error::msg(format!("Error loading levels: {e}")))?;
+            load_context.set_default_asset(LoadedAsset::new(levels));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pack.ron", "pack.json"]
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::{self, Write};
+
+use ndarray::{s, ArrayView1};
+
+use super::{Cell, Dir, Level, Levels};
+
+/// Line ending used by the original game's packs.
+const NEWLINE: &str = "\r\n";
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Empty => f.write_char(' '),
+            Cell::Wall => f.write_char('w'),
+            Cell::Start => f.write_char('s'),
+            Cell::Exit => f.write_char('e'),
+            Cell::Bomb => f.write_char('b'),
+            Cell::Cement => f.write_char('c'),
+            Cell::Barrel => f.write_char('d'),
+            Cell::Money => f.write_char('f'),
+            Cell::Guard => f.write_char('g'),
+            Cell::Hole => f.write_char('h'),
+            Cell::MetalWall => f.write_char('i'),
+            Cell::JellyBean => f.write_char('j'),
+            Cell::Key => f.write_char('k'),
+            Cell::Lock => f.write_char('l'),
+            Cell::Gun => f.write_char('n'),
+            Cell::Oxygen => f.write_char('o'),
+            Cell::Teleport(id, dir) => {
+                let dir = match dir {
+                    Dir::Up => 1,
+                    Dir::Down => 2,
+                    Dir::Left => 3,
+                    Dir::Right => 4,
+                };
+                write!(f, "t{id}{dir}")
+            }
+            Cell::Water => f.write_char('~'),
+        }
+    }
+}
+
+impl Level {
+    /// Each row as it should be written, without the padding a short row was
+    /// given when the level was loaded, unless something has been put there
+    /// since.
+    pub(crate) fn written_rows(&self) -> impl Iterator<Item = ArrayView1<'_, Cell>> {
+        (0..self.map.nrows()).map(|j| {
+            let row = self.map.row(j);
+            let used = row
+                .iter()
+                .rposition(|cell| *cell != Cell::Empty)
+                .map_or(0, |i| i + 1);
+            let width = self
+                .row_widths
+                .get(j)
+                .map_or(row.len(), |width| (*width).clamp(used, row.len()));
+            self.map.slice(s![j, ..width])
+        })
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{NEWLINE}{}{NEWLINE}", self.name, self.author)?;
+        for row in self.written_rows() {
+            for cell in row {
+                write!(f, "{cell}")?;
+            }
+            f.write_str(NEWLINE)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `levels` in the same "Mushroom Man 3.0" format [`parse_levels`]
+/// reads. The checksum is written back as it was loaded, so a pack made for
+/// the original game comes out byte for byte the same. Text is written in
+/// Latin-1 like the original game's packs, with `?` for anything Latin-1 can't
+/// hold.
+///
+/// [`parse_levels`]: super::parse::parse_levels
+pub(crate) fn write_levels(levels: &Levels) -> Vec<u8> {
+    let mut body = String::new();
+    for level in &levels.levels {
+        body.push_str(NEWLINE);
+        body.push_str(&level.to_string());
+    }
+    let body: Vec<u8> = body
+        .chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect();
+    let mut out = format!("Mushroom Man 3.0{NEWLINE}{}{NEWLINE}", levels.checksum).into_bytes();
+    out.extend(body);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{parse::parse_levels, PackFormat};
+
+    const BUNDLED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/levels.dat");
+
+    #[test]
+    fn bundled_pack_round_trips() {
+        let bytes = std::fs::read(BUNDLED).unwrap();
+        let levels = parse_levels(&bytes).unwrap();
+        assert_eq!(write_levels(&levels), bytes);
+    }
+
+    #[test]
+    fn bundled_pack_round_trips_through_ron() {
+        let bytes = std::fs::read(BUNDLED).unwrap();
+        let levels = parse_levels(&bytes).unwrap();
+        let ron = PackFormat::Ron.write(&levels);
+        let levels = PackFormat::Ron.parse(&ron).unwrap();
+        assert_eq!(write_levels(&levels), bytes);
+    }
+
+    #[test]
+    fn short_rows_keep_what_is_put_in_their_padding() {
+        let mut levels =
+            parse_levels(b"Mushroom Man 3.0\r\n0\r\n\r\nA\r\nB\r\nse\r\nw\r\n").unwrap();
+        assert_eq!(levels.levels[0].to_string(), "A\r\nB\r\nse\r\nw\r\n");
+        levels.levels[0].map[(1, 1)] = Cell::Wall;
+        assert_eq!(levels.levels[0].to_string(), "A\r\nB\r\nse\r\nww\r\n");
+    }
+
+    #[test]
+    fn keeps_the_checksum_it_was_loaded_with() {
+        let levels = parse_levels(b"Mushroom Man 3.0\r\n12345\r\n\r\nA\r\nB\r\nse\r\n").unwrap();
+        assert!(write_levels(&levels).starts_with(b"Mushroom Man 3.0\r\n12345\r\n"));
+    }
+}
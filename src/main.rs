@@ -3,15 +3,42 @@ use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioPlugin;
 use iyes_progress::ProgressPlugin;
 
+mod cli;
+mod convert;
+mod editor;
 mod events;
+mod history;
+mod hud;
 mod image;
 mod input;
 mod level;
+mod menu;
+mod replay;
+mod save;
+mod score;
+mod settings;
+mod solver;
+mod sound;
+mod ui;
+mod validate;
 
+use cli::{Args, USAGE};
+use convert::convert_pack;
+use editor::EditorPlugin;
 use events::EventPlugin;
+use history::HistoryPlugin;
+use hud::HudPlugin;
 use image::TexturePlugin;
 use input::InputPlugin;
-use level::LevelPlugin;
+use level::{LevelPlugin, PackChoice};
+use menu::MenuPlugin;
+use replay::{Playback, Replay, ReplayPlugin};
+use save::SavePlugin;
+use score::ScorePlugin;
+use settings::SettingsPlugin;
+use sound::SoundPlugin;
+use ui::UiPlugin;
+use validate::validate_pack;
 
 #[derive(Debug, Default, Clone, Copy, States, Eq, PartialEq, Hash)]
 pub enum GameState {
@@ -21,29 +48,97 @@ pub enum GameState {
     Playing,
     Paused,
     GameOver,
+    LevelComplete,
+    /// The level pack could not be played.
+    Error,
+    Editor,
 }
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
-        .add_state::<GameState>()
-        // TODO: Change this to Menu, and create the menu
-        .add_loading_state(
-            LoadingState::new(GameState::Loading).continue_to_state(GameState::Playing),
-        )
-        .add_plugin(AudioPlugin)
-        .add_plugin(EventPlugin)
-        .add_plugin(
-            ProgressPlugin::new(GameState::Loading)
-                .continue_to(GameState::Menu)
-                .track_assets(),
-        )
-        .add_plugin(InputPlugin)
-        .add_plugin(LevelPlugin)
-        .add_plugin(TexturePlugin)
-        .add_startup_system(setup)
-        // .add_system(move_camera.system())
-        .run();
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{USAGE}");
+        return;
+    }
+    if let Some(pack) = &args.validate {
+        let valid = validate_pack(pack, args.max_states);
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+    if let Some((from, to)) = &args.convert {
+        if let Err(e) = convert_pack(from, to) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let playback = args.replay.as_ref().map(|path| match Replay::read(path) {
+        Ok(replay) => Playback::new(replay, args.speed),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    });
+
+    // The asset server looks for relative paths under assets/, so anchor
+    // packs given on the command line to where the game was started.
+    let pack = args.pack.map(|path| {
+        std::env::current_dir()
+            .map(|dir| dir.join(&path))
+            .unwrap_or(path)
+    });
+
+    let mut app = App::new();
+    app.insert_resource(PackChoice {
+        path: pack,
+        // A replay starts on the level it was recorded on.
+        level: playback
+            .as_ref()
+            .map_or(args.level, |playback| playback.replay.level),
+    })
+    .add_plugins(
+        DefaultPlugins
+            .set(ImagePlugin::default_nearest())
+            .set(AssetPlugin {
+                watch_for_changes: true,
+                ..Default::default()
+            })
+            // bevy_kira_audio plays the sound.
+            .disable::<bevy::audio::AudioPlugin>(),
+    )
+    .add_state::<GameState>()
+    .add_loading_state(LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu))
+    .add_plugin(AudioPlugin)
+    .add_plugin(EditorPlugin)
+    .add_plugin(EventPlugin)
+    .add_plugin(HistoryPlugin)
+    .add_plugin(HudPlugin)
+    .add_plugin(
+        ProgressPlugin::new(GameState::Loading)
+            .continue_to(GameState::Menu)
+            .track_assets(),
+    )
+    .add_plugin(InputPlugin)
+    .add_plugin(LevelPlugin)
+    .add_plugin(MenuPlugin)
+    .add_plugin(ReplayPlugin)
+    .add_plugin(SavePlugin)
+    .add_plugin(ScorePlugin)
+    .add_plugin(SettingsPlugin)
+    .add_plugin(SoundPlugin)
+    .add_plugin(TexturePlugin)
+    .add_plugin(UiPlugin)
+    .add_startup_system(setup);
+    // .add_system(move_camera.system())
+    if let Some(playback) = playback {
+        app.insert_resource(playback);
+    }
+    app.run();
 }
 
 #[derive(Debug, Copy, Clone, Component)]
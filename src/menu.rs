@@ -0,0 +1,346 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    editor::Editor,
+    events::{LevelEvent, MenuEvent},
+    level::{ActivePack, CurrentLevel, LevelMap, Levels, PackFingerprint, PendingLevel},
+    save::{PackProgress, SaveFile},
+    settings::{Settings, VOLUME_STEP},
+    ui::{format_time, UiAssets},
+    GameState,
+};
+
+/// How many entries fit on screen at once, for long pages like level select.
+const VISIBLE_ENTRIES: usize = 15;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MenuStack::default())
+            .add_event::<MenuAction>()
+            .add_system(open_main_menu.in_schedule(OnEnter(GameState::Menu)))
+            .add_system(close_menu.in_schedule(OnExit(GameState::Menu)))
+            .add_system(open_pause_menu.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(close_menu.in_schedule(OnExit(GameState::Paused)))
+            .add_systems(
+                (navigate_menu, run_menu_action, draw_menu)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Menu)),
+            )
+            .add_systems(
+                (navigate_menu, run_menu_action, draw_menu)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Paused)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Page {
+    Main,
+    LevelSelect,
+    Settings,
+    Pause,
+}
+
+/// What choosing a menu entry does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MenuAction {
+    Continue,
+    NewGame,
+    StartLevel(usize),
+    Open(Page),
+    Editor,
+    ToggleFullscreen,
+    /// Turns the volume up, or down when false.
+    ChangeVolume(bool),
+    Back,
+    Quit,
+    Resume,
+    Restart,
+    QuitToMenu,
+}
+
+/// The pages the player has opened, with the selected entry on each. The last
+/// one is on screen.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct MenuStack(Vec<(Page, usize)>);
+
+#[derive(Debug, Copy, Clone, Component)]
+struct MenuRoot;
+
+impl Page {
+    fn title(&self) -> &'static str {
+        match self {
+            Page::Main => "Mushroom Man",
+            Page::LevelSelect => "Level Select",
+            Page::Settings => "Settings",
+            Page::Pause => "Paused",
+        }
+    }
+
+    fn entries(
+        &self,
+        levels: Option<&Levels>,
+        progress: Option<&PackProgress>,
+        settings: &Settings,
+    ) -> Vec<(String, MenuAction)> {
+        let entry = |label: &str, action| (label.to_string(), action);
+        match self {
+            Page::Main => vec![
+                entry("Continue", MenuAction::Continue),
+                entry("New Game", MenuAction::NewGame),
+                entry("Level Select", MenuAction::Open(Page::LevelSelect)),
+                entry("Editor", MenuAction::Editor),
+                entry("Settings", MenuAction::Open(Page::Settings)),
+                entry("Quit", MenuAction::Quit),
+            ],
+            Page::LevelSelect => levels
+                .map(|levels| {
+                    levels
+                        .levels
+                        .iter()
+                        .enumerate()
+                        .map(|(n, level)| {
+                            let best = progress
+                                .and_then(|progress| progress.levels.get(&n))
+                                .map(|record| {
+                                    format!(
+                                        "  ({} moves, {})",
+                                        record.best_moves,
+                                        format_time(record.best_time)
+                                    )
+                                })
+                                .unwrap_or_default();
+                            (
+                                format!("{n:3}  {}{best}", level.name),
+                                MenuAction::StartLevel(n),
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Page::Settings => vec![
+                (
+                    format!(
+                        "Fullscreen: {}",
+                        if settings.fullscreen { "On" } else { "Off" }
+                    ),
+                    MenuAction::ToggleFullscreen,
+                ),
+                (
+                    format!("Volume: {}%", settings.volume),
+                    MenuAction::ChangeVolume(true),
+                ),
+                entry("Back", MenuAction::Back),
+            ],
+            Page::Pause => vec![
+                entry("Resume", MenuAction::Resume),
+                entry("Restart Level", MenuAction::Restart),
+                entry("Settings", MenuAction::Open(Page::Settings)),
+                entry("Quit to Menu", MenuAction::QuitToMenu),
+            ],
+        }
+    }
+}
+
+fn open_main_menu(mut stack: ResMut<MenuStack>) {
+    stack.0 = vec![(Page::Main, 0)];
+}
+
+fn open_pause_menu(mut stack: ResMut<MenuStack>) {
+    stack.0 = vec![(Page::Pause, 0)];
+}
+
+fn close_menu(mut commands: Commands, roots: Query<Entity, With<MenuRoot>>) {
+    for root in &roots {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+fn navigate_menu(
+    mut menu_events: EventReader<MenuEvent>,
+    mut stack: ResMut<MenuStack>,
+    mut actions: EventWriter<MenuAction>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    fingerprint: Res<PackFingerprint>,
+    save: Res<SaveFile>,
+    settings: Res<Settings>,
+) {
+    if menu_events.is_empty() {
+        return;
+    }
+    let levels = levels.get(&pack);
+    let progress = save.progress(&fingerprint);
+    for event in menu_events.iter() {
+        let Some((page, selected)) = stack.0.last().copied() else {
+            continue;
+        };
+        let entries = page.entries(levels, progress, &settings);
+        let count = entries.len().max(1);
+        match event {
+            MenuEvent::Up => stack.0.last_mut().unwrap().1 = (selected + count - 1) % count,
+            MenuEvent::Down => stack.0.last_mut().unwrap().1 = (selected + 1) % count,
+            // Backing out of the pause menu carries on playing.
+            MenuEvent::Back if page == Page::Pause => actions.send(MenuAction::Resume),
+            MenuEvent::Back => actions.send(MenuAction::Back),
+            MenuEvent::Select => {
+                if let Some((_, action)) = entries.get(selected) {
+                    actions.send(*action);
+                }
+            }
+            // Only settings can be changed sideways.
+            MenuEvent::Left | MenuEvent::Right => match entries.get(selected) {
+                Some((_, action @ MenuAction::ToggleFullscreen)) => actions.send(*action),
+                Some((_, MenuAction::ChangeVolume(_))) => {
+                    actions.send(MenuAction::ChangeVolume(*event == MenuEvent::Right))
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+fn run_menu_action(
+    mut actions: EventReader<MenuAction>,
+    mut stack: ResMut<MenuStack>,
+    mut settings: ResMut<Settings>,
+    mut pending: ResMut<PendingLevel>,
+    current_level: Res<CurrentLevel>,
+    level_map: Res<LevelMap>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    fingerprint: Res<PackFingerprint>,
+    save: Res<SaveFile>,
+    mut editor: ResMut<Editor>,
+    mut level_events: EventWriter<LevelEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for action in actions.iter() {
+        match action {
+            MenuAction::Continue => {
+                // Go back to a level that is still on the board, or start
+                // from the furthest one reached last time.
+                if level_map.map.is_empty() {
+                    let unlocked = levels.get(&pack).and_then(|levels| {
+                        let progress = save.progress(&fingerprint)?;
+                        Some(progress.unlocked.min(levels.levels.len().saturating_sub(1)))
+                    });
+                    pending.0 = Some(unlocked.unwrap_or(**current_level));
+                }
+                next_state.set(GameState::Playing);
+            }
+            MenuAction::NewGame => {
+                pending.0 = Some(0);
+                next_state.set(GameState::Playing);
+            }
+            MenuAction::StartLevel(n) => {
+                pending.0 = Some(*n);
+                next_state.set(GameState::Playing);
+            }
+            MenuAction::Open(page) => stack.0.push((*page, 0)),
+            MenuAction::Editor => {
+                editor.back_to = GameState::Menu;
+                next_state.set(GameState::Editor);
+            }
+            MenuAction::ToggleFullscreen => settings.fullscreen = !settings.fullscreen,
+            MenuAction::ChangeVolume(up) => {
+                settings.volume = if *up {
+                    (settings.volume + VOLUME_STEP).min(100)
+                } else {
+                    settings.volume.saturating_sub(VOLUME_STEP)
+                };
+            }
+            MenuAction::Back => {
+                if stack.0.len() > 1 {
+                    stack.0.pop();
+                }
+            }
+            MenuAction::Quit => exit.send(AppExit),
+            // The level stays as it was, explosions and all, while paused.
+            MenuAction::Resume => next_state.set(GameState::Playing),
+            MenuAction::Restart => {
+                level_events.send(LevelEvent(**current_level));
+                next_state.set(GameState::Playing);
+            }
+            MenuAction::QuitToMenu => next_state.set(GameState::Menu),
+        }
+    }
+}
+
+/// Rebuilds the page on screen whenever the selection or a setting changes.
+fn draw_menu(
+    mut commands: Commands,
+    stack: Res<MenuStack>,
+    settings: Res<Settings>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    fingerprint: Res<PackFingerprint>,
+    save: Res<SaveFile>,
+    roots: Query<Entity, With<MenuRoot>>,
+    ui_assets: Res<UiAssets>,
+) {
+    if !(stack.is_changed() || settings.is_changed()) {
+        return;
+    }
+    for root in &roots {
+        commands.entity(root).despawn_recursive();
+    }
+    let Some((page, selected)) = stack.0.last().copied() else {
+        return;
+    };
+
+    let levels = levels.get(&pack);
+    let progress = save.progress(&fingerprint);
+    let entries = page.entries(levels, progress, &settings);
+    let first = selected
+        .saturating_sub(VISIBLE_ENTRIES / 2)
+        .min(entries.len().saturating_sub(VISIBLE_ENTRIES));
+    let style = |font_size, color| TextStyle {
+        font: ui_assets.font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn((
+            MenuRoot,
+            NodeBundle {
+                style: Style {
+                    size: Size::all(Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    gap: Size::height(Val::Px(8.0)),
+                    ..Default::default()
+                },
+                // Let the level show through while paused.
+                background_color: match page {
+                    Page::Pause => Color::rgba(0.0, 0.0, 0.0, 0.6),
+                    _ => Color::rgba(0.05, 0.05, 0.1, 0.9),
+                }
+                .into(),
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(page.title(), style(48.0, Color::WHITE)).with_style(
+                    Style {
+                        margin: UiRect::bottom(Val::Px(24.0)),
+                        ..Default::default()
+                    },
+                ),
+            );
+            for (n, (label, _)) in entries.iter().enumerate().skip(first).take(VISIBLE_ENTRIES) {
+                let (label, color) = if n == selected {
+                    (format!("> {label} <"), Color::YELLOW)
+                } else {
+                    (label.clone(), Color::GRAY)
+                };
+                parent.spawn(TextBundle::from_section(label, style(28.0, color)));
+            }
+        });
+}
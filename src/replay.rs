@@ -0,0 +1,287 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, time::Stopwatch};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{HistoryEvent, LevelEvent, MovementEvent},
+    level::{fingerprint_pack, move_player, Dir, PackFingerprint, PendingLevel},
+    save::{data_dir, LevelClock},
+    GameState,
+};
+
+/// Bumped whenever the layout of [`Replay`] changes.
+const REPLAY_VERSION: u32 = 1;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recording::default())
+            .add_systems(
+                (start_recording, record_steps)
+                    .chain()
+                    .in_set(OnUpdate(GameState::Playing))
+                    .after(fingerprint_pack)
+                    .distributive_run_if(not_replaying),
+            )
+            .add_system(finish_recording.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(finish_recording.in_schedule(OnEnter(GameState::LevelComplete)))
+            .add_system(finish_recording.in_schedule(OnEnter(GameState::Editor)))
+            .add_system(
+                start_playback
+                    .in_schedule(OnExit(GameState::Loading))
+                    .run_if(resource_exists::<Playback>()),
+            )
+            .add_system(
+                play_steps
+                    .in_set(OnUpdate(GameState::Playing))
+                    .after(fingerprint_pack)
+                    .before(move_player)
+                    .run_if(resource_exists::<Playback>()),
+            )
+            .add_system(stop_playback.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(stop_playback.in_schedule(OnEnter(GameState::LevelComplete)));
+    }
+}
+
+/// One thing the player did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Step {
+    Move(Dir),
+    Undo,
+    Redo,
+}
+
+/// How an attempt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Outcome {
+    Completed,
+    Died,
+    /// Restarted, or left for the menu or editor.
+    Abandoned,
+}
+
+/// A single attempt at a level, enough to play it again move for move.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// The [`Levels::fingerprint`] of the pack it was played on, to catch
+    /// replays of a pack that has since changed.
+    ///
+    /// [`Levels::fingerprint`]: crate::level::Levels::fingerprint
+    pub pack: u32,
+    pub level: usize,
+    pub outcome: Outcome,
+    /// Each step, with the milliseconds since the level started.
+    pub steps: Vec<(u64, Step)>,
+}
+
+impl Replay {
+    fn new(pack: u32, level: usize) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            pack,
+            level,
+            outcome: Outcome::Abandoned,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let replay: Replay =
+            ron::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        if replay.version > REPLAY_VERSION {
+            return Err(format!(
+                "{}: made by a newer version of the game ({})",
+                path.display(),
+                replay.version
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// Writes the replay into `dir`, named after the pack, level and time so
+    /// attempts never overwrite each other.
+    fn write(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = dir.join(format!("{:08x}-{}-{now}.ron", self.pack, self.level));
+        // Kept on one line, replays are for sharing rather than reading.
+        let text = ron::to_string(self).expect("replays always serialize");
+        fs::write(&path, text).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// The attempt being played right now.
+#[derive(Debug, Clone, Default, Resource)]
+struct Recording(Option<Replay>);
+
+/// A replay being watched, from `--replay`. Player input is ignored until it
+/// runs out.
+#[derive(Debug, Clone, Resource)]
+pub struct Playback {
+    pub replay: Replay,
+    /// How many times faster than it was played.
+    speed: u32,
+    /// The next step to send.
+    next: usize,
+    clock: Stopwatch,
+    started: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, speed: u32) -> Self {
+        Self {
+            replay,
+            speed,
+            next: 0,
+            clock: Stopwatch::new(),
+            started: false,
+        }
+    }
+}
+
+/// Run condition for anything the player should not do while a replay plays.
+pub fn not_replaying(playback: Option<Res<Playback>>) -> bool {
+    playback.is_none()
+}
+
+fn save_replay(mut replay: Replay, outcome: Outcome) {
+    // Nothing worth keeping happened.
+    if replay.steps.is_empty() {
+        return;
+    }
+    let Some(dir) = data_dir() else {
+        return;
+    };
+    replay.outcome = outcome;
+    match replay.write(&dir.join("replays")) {
+        Ok(path) => log::info!("Saved replay to {}", path.display()),
+        Err(e) => log::error!("Could not save replay: {e}"),
+    }
+}
+
+fn start_recording(
+    mut level_events: EventReader<LevelEvent>,
+    mut recording: ResMut<Recording>,
+    fingerprint: Res<PackFingerprint>,
+) {
+    let Some(LevelEvent(level)) = level_events.iter().last() else {
+        return;
+    };
+    let Some(fingerprint) = **fingerprint else {
+        return;
+    };
+    if let Some(replay) = recording.0.take() {
+        save_replay(replay, Outcome::Abandoned);
+    }
+    recording.0 = Some(Replay::new(fingerprint, *level));
+}
+
+fn record_steps(
+    mut movements: EventReader<MovementEvent>,
+    mut history_events: EventReader<HistoryEvent>,
+    mut recording: ResMut<Recording>,
+    clock: Res<LevelClock>,
+) {
+    let Some(replay) = &mut recording.0 else {
+        return;
+    };
+    let at = clock.elapsed().as_millis() as u64;
+    // Moves come first, as move_player runs before travel_history.
+    let moves = movements.iter().map(|movement| Step::Move(movement.dir));
+    let history = history_events.iter().map(|event| match event {
+        HistoryEvent::Undo => Step::Undo,
+        HistoryEvent::Redo => Step::Redo,
+    });
+    replay
+        .steps
+        .extend(moves.chain(history).map(|step| (at, step)));
+}
+
+/// Saves the attempt when the level ends one way or another. Going to the main
+/// menu doesn't end it, as Continue carries on with the level on the board.
+fn finish_recording(state: Res<State<GameState>>, mut recording: ResMut<Recording>) {
+    let Some(replay) = recording.0.take() else {
+        return;
+    };
+    let outcome = match state.0 {
+        GameState::LevelComplete => Outcome::Completed,
+        GameState::GameOver => Outcome::Died,
+        _ => Outcome::Abandoned,
+    };
+    save_replay(replay, outcome);
+}
+
+fn start_playback(
+    playback: Res<Playback>,
+    mut pending: ResMut<PendingLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    pending.0 = Some(playback.replay.level);
+    next_state.set(GameState::Playing);
+}
+
+/// Sends each step once its time comes, starting over whenever the level is
+/// restarted. Only one step goes a frame, so a move and an undo are never
+/// left to the order of the systems that handle them.
+fn play_steps(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut level_events: EventReader<LevelEvent>,
+    fingerprint: Res<PackFingerprint>,
+    time: Res<Time>,
+    mut movements: EventWriter<MovementEvent>,
+    mut history_events: EventWriter<HistoryEvent>,
+) {
+    if let Some(LevelEvent(level)) = level_events.iter().last() {
+        if *level != playback.replay.level {
+            log::info!("Left the replayed level, stopping the replay");
+            commands.remove_resource::<Playback>();
+            return;
+        }
+        if matches!(**fingerprint, Some(fingerprint) if fingerprint != playback.replay.pack) {
+            log::warn!("The replay was recorded on a different version of this pack");
+        }
+        playback.next = 0;
+        playback.clock.reset();
+        // The level is only built at the end of this frame.
+        playback.started = true;
+        return;
+    }
+    if !playback.started {
+        return;
+    }
+
+    let delta = time.delta() * playback.speed;
+    playback.clock.tick(delta);
+    let now = playback.clock.elapsed();
+    let Some((at, step)) = playback.replay.steps.get(playback.next).copied() else {
+        log::info!("Replay finished");
+        commands.remove_resource::<Playback>();
+        return;
+    };
+    if Duration::from_millis(at) > now {
+        return;
+    }
+    match step {
+        Step::Move(dir) => movements.send(MovementEvent { dir }),
+        Step::Undo => history_events.send(HistoryEvent::Undo),
+        Step::Redo => history_events.send(HistoryEvent::Redo),
+    }
+    playback.next += 1;
+}
+
+fn stop_playback(mut commands: Commands) {
+    commands.remove_resource::<Playback>();
+}
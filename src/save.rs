@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use bevy::{prelude::*, time::Stopwatch};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{LevelEvent, LevelSummary},
+    level::PackFingerprint,
+    replay::not_replaying,
+    GameState,
+};
+
+/// Bumped whenever the layout of [`SaveData`] changes, so older files can be
+/// upgraded and newer ones left alone.
+const SAVE_VERSION: u32 = 1;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_save())
+            .insert_resource(LevelClock::default())
+            .add_system(reset_clock.in_set(OnUpdate(GameState::Playing)))
+            .add_system(
+                tick_clock
+                    .in_set(OnUpdate(GameState::Playing))
+                    .after(reset_clock),
+            )
+            .add_system(
+                record_completion
+                    .in_schedule(OnEnter(GameState::LevelComplete))
+                    .run_if(not_replaying),
+            );
+    }
+}
+
+/// Everything remembered between runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    /// Progress in each pack, by [`Levels::fingerprint`].
+    ///
+    /// [`Levels::fingerprint`]: crate::level::Levels::fingerprint
+    pub packs: BTreeMap<u32, PackProgress>,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            packs: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackProgress {
+    /// The furthest level the player may start from.
+    pub unlocked: usize,
+    /// Best results for each level that has been finished.
+    pub levels: BTreeMap<usize, LevelRecord>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelRecord {
+    pub best_moves: usize,
+    pub best_time: Duration,
+}
+
+impl PackProgress {
+    /// Remembers finishing `number`, keeping whichever results are best.
+    pub fn finish(&mut self, number: usize, moves: usize, time: Duration) {
+        self.unlocked = self.unlocked.max(number + 1);
+        let record = self.levels.entry(number).or_insert(LevelRecord {
+            best_moves: moves,
+            best_time: time,
+        });
+        record.best_moves = record.best_moves.min(moves);
+        record.best_time = record.best_time.min(time);
+    }
+}
+
+/// The save data, and where it goes. Without a path nothing is written, either
+/// because there is no data dir or because the file is from a newer version.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SaveFile {
+    path: Option<PathBuf>,
+    pub data: SaveData,
+}
+
+impl SaveFile {
+    pub fn progress(&self, pack: &PackFingerprint) -> Option<&PackProgress> {
+        self.data.packs.get(&(**pack)?)
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = ron::ser::to_string_pretty(&self.data, Default::default())
+            .expect("save data always serializes");
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        // Write next to the old file first, so a crash can't leave half a save.
+        let temp = path.with_extension("ron.tmp");
+        fs::write(&temp, text).map_err(|e| format!("{}: {e}", temp.display()))?;
+        fs::rename(&temp, path).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// Only the version, read before the rest so a file from a newer game is
+/// never misread.
+#[derive(Debug, Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// Where saves and replays are kept, e.g. `~/.local/share/mushman` on Linux.
+pub fn data_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "mushman").map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+fn save_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("save.ron"))
+}
+
+fn load_save() -> SaveFile {
+    let Some(path) = save_path() else {
+        log::warn!("No data directory, progress will not be saved");
+        return SaveFile::default();
+    };
+    read_save(path)
+}
+
+fn read_save(path: PathBuf) -> SaveFile {
+    let Ok(text) = fs::read_to_string(&path) else {
+        return SaveFile {
+            path: Some(path),
+            data: SaveData::default(),
+        };
+    };
+
+    let data = match ron::from_str::<Version>(&text) {
+        Ok(Version { version }) if version > SAVE_VERSION => {
+            log::warn!(
+                "{} is from a newer version ({version}), progress will not be saved",
+                path.display()
+            );
+            return SaveFile {
+                path: None,
+                data: SaveData::default(),
+            };
+        }
+        // Upgrades from older versions go here, once there are any.
+        Ok(_) => ron::from_str::<SaveData>(&text).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match data {
+        Ok(data) => SaveFile {
+            path: Some(path),
+            data,
+        },
+        Err(e) => {
+            // Keep the broken file around instead of overwriting it.
+            let backup = path.with_extension("ron.bak");
+            log::error!(
+                "Could not read {}: {e}. Moving it to {}",
+                path.display(),
+                backup.display()
+            );
+            if let Err(e) = fs::rename(&path, &backup) {
+                log::error!("{e}");
+            }
+            SaveFile {
+                path: Some(path),
+                data: SaveData::default(),
+            }
+        }
+    }
+}
+
+/// Time spent playing the current level, not counting pauses.
+#[derive(Debug, Clone, Default, Resource, Deref, DerefMut)]
+pub struct LevelClock(Stopwatch);
+
+fn reset_clock(mut level_events: EventReader<LevelEvent>, mut clock: ResMut<LevelClock>) {
+    if level_events.iter().count() > 0 {
+        clock.reset();
+    }
+}
+
+fn tick_clock(time: Res<Time>, mut clock: ResMut<LevelClock>) {
+    clock.tick(time.delta());
+}
+
+fn record_completion(
+    summary: Res<LevelSummary>,
+    fingerprint: Res<PackFingerprint>,
+    mut save: ResMut<SaveFile>,
+) {
+    let Some(fingerprint) = **fingerprint else {
+        return;
+    };
+    save.data.packs.entry(fingerprint).or_default().finish(
+        summary.number,
+        summary.moves,
+        summary.time,
+    );
+    if let Err(e) = save.write() {
+        log::error!("Could not save progress: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_best_of_each_result() {
+        let mut progress = PackProgress::default();
+        progress.finish(2, 30, Duration::from_secs(20));
+        progress.finish(2, 40, Duration::from_secs(10));
+        assert_eq!(progress.unlocked, 3);
+        assert_eq!(
+            progress.levels[&2],
+            LevelRecord {
+                best_moves: 30,
+                best_time: Duration::from_secs(10),
+            }
+        );
+
+        // Replaying an earlier level doesn't lock the later ones again.
+        progress.finish(0, 5, Duration::from_secs(1));
+        assert_eq!(progress.unlocked, 3);
+    }
+
+    #[test]
+    fn leaves_a_newer_save_alone() {
+        let path = std::env::temp_dir().join(format!("mushman-save-{}.ron", std::process::id()));
+        let text = "(version: 2, packs: {}, medals: {})";
+        fs::write(&path, text).unwrap();
+
+        let save = read_save(path.clone());
+        assert_eq!(save.path, None);
+        assert_eq!(save.data, SaveData::default());
+        // Nothing gets written over it either.
+        save.write().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+
+use crate::{
+    events::LevelEvent,
+    level::{ActivePack, Inventory, Levels, StepOutcome},
+    solver::{solve, Solution, DEFAULT_STATE_LIMIT},
+    GameState,
+};
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Score::default())
+            .insert_resource(Par::default())
+            .add_system(forget_pars)
+            .add_system(
+                find_par
+                    .in_set(OnUpdate(GameState::Playing))
+                    .after(forget_pars),
+            )
+            .add_system(poll_par.after(find_par));
+    }
+}
+
+/// What the player has done so far in the current attempt. Undoing a move
+/// takes it back off the count.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub struct Score {
+    pub moves: usize,
+    pub pushes: usize,
+    pub items_used: usize,
+}
+
+impl Score {
+    /// Counts a step that did something.
+    pub fn count(&mut self, outcome: &StepOutcome) {
+        self.moves += 1;
+        if outcome.pushed {
+            self.pushes += 1;
+        }
+        self.items_used += outcome
+            .items
+            .iter()
+            .filter(|(_, amount)| *amount < 0)
+            .count();
+    }
+}
+
+/// The number of moves a good solution to the current level takes, from the
+/// pack when it says, or else from the solver.
+#[derive(Debug, Default, Resource)]
+pub struct Par {
+    pub moves: Option<usize>,
+    level: usize,
+    /// What the solver made of each level in the pack so far, so a restart
+    /// doesn't solve the level all over again.
+    solved: HashMap<usize, Option<usize>>,
+    task: Option<(usize, Task<Option<usize>>)>,
+}
+
+/// Edited levels need solving again.
+fn forget_pars(
+    mut asset_events: EventReader<AssetEvent<Levels>>,
+    pack: Res<ActivePack>,
+    mut par: ResMut<Par>,
+) {
+    let modified = asset_events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { handle } if *handle == **pack));
+    if modified {
+        par.solved.clear();
+        par.task = None;
+    }
+}
+
+fn find_par(
+    mut level_events: EventReader<LevelEvent>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    mut par: ResMut<Par>,
+) {
+    let Some(LevelEvent(number)) = level_events.iter().last() else {
+        return;
+    };
+    let Some(level) = levels
+        .get(&pack)
+        .and_then(|levels| levels.levels.get(*number))
+    else {
+        return;
+    };
+    par.level = *number;
+    if let Some(moves) = level.info.par {
+        par.moves = Some(moves);
+        return;
+    }
+    if let Some(moves) = par.solved.get(number) {
+        par.moves = *moves;
+        return;
+    }
+    par.moves = None;
+    if matches!(par.task, Some((level, _)) if level == *number) {
+        return;
+    }
+
+    // Solving can take a while, so it happens in the background while the
+    // level is played.
+    let mut level = level.clone();
+    level.player_pos = level.start_pos;
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        match solve(&level, &Inventory::default(), DEFAULT_STATE_LIMIT) {
+            Solution::Solved(moves) => Some(moves.len()),
+            Solution::Unsolvable | Solution::GaveUp(_) => None,
+        }
+    });
+    par.task = Some((*number, task));
+}
+
+fn poll_par(mut par: ResMut<Par>) {
+    if !matches!(&par.task, Some((_, task)) if task.is_finished()) {
+        return;
+    }
+    let Some((level, task)) = par.task.take() else {
+        return;
+    };
+    let moves = future::block_on(task);
+    match moves {
+        Some(moves) => log::info!("The solver puts par for level {level} at {moves}"),
+        None => log::info!("The solver found no par for level {level}"),
+    }
+    par.solved.insert(level, moves);
+    if par.level == level {
+        par.moves = moves;
+    }
+}
@@ -0,0 +1,41 @@
+use bevy::{prelude::*, window::WindowMode};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::default())
+            .add_system(apply_settings);
+    }
+}
+
+/// How much each press changes the volume by.
+pub const VOLUME_STEP: u8 = 10;
+
+/// Player preferences, changed from the settings menu.
+#[derive(Debug, Clone, PartialEq, Eq, Resource)]
+pub struct Settings {
+    pub fullscreen: bool,
+    /// Sound volume, from 0 to 100.
+    pub volume: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            volume: 80,
+        }
+    }
+}
+
+fn apply_settings(settings: Res<Settings>, mut window: Query<&mut Window>) {
+    if !settings.is_changed() {
+        return;
+    }
+    window.single_mut().mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+}
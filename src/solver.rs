@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use bevy::utils::HashSet;
+use ndarray::Array2;
+
+use crate::level::{Cell, Coord, Dir, Inventory, Item, Level};
+
+/// How many distinct states [`solve`] looks at before giving up, unless told
+/// otherwise.
+pub const DEFAULT_STATE_LIMIT: usize = 2_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Solution {
+    /// A shortest sequence of moves that reaches an exit.
+    Solved(Vec<Dir>),
+    /// Every reachable state was searched without finding an exit.
+    Unsolvable,
+    /// The search stopped after this many states without an answer.
+    GaveUp(usize),
+}
+
+/// A point in the search. The map is stored as the cells that differ from the
+/// level's starting map, since most moves only touch a square or two.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    changed: Vec<(usize, Cell)>,
+    player_pos: Coord,
+    items: [usize; Item::ALL.len()],
+}
+
+impl State {
+    fn new(initial: &Array2<Cell>, level: &Level, inventory: &Inventory) -> Self {
+        Self {
+            changed: level
+                .map
+                .iter()
+                .zip(initial)
+                .enumerate()
+                .filter(|(_, (cell, old))| cell != old)
+                .map(|(i, (cell, _))| (i, *cell))
+                .collect(),
+            player_pos: level.player_pos,
+            items: Item::ALL.map(|item| inventory.count(item)),
+        }
+    }
+
+    /// Puts `level` and a fresh inventory into this state.
+    fn restore(&self, initial: &Array2<Cell>, level: &mut Level) -> Inventory {
+        level.map.assign(initial);
+        let cells = level
+            .map
+            .as_slice_mut()
+            .expect("maps are in standard layout");
+        for (i, cell) in &self.changed {
+            cells[*i] = *cell;
+        }
+        level.player_pos = self.player_pos;
+
+        let mut inventory = Inventory::default();
+        for (item, count) in Item::ALL.into_iter().zip(self.items) {
+            inventory.add(item, count);
+        }
+        inventory
+    }
+}
+
+/// Breadth-first search over every map, position and inventory reachable with
+/// the game's own rules, starting from where the player currently stands.
+/// Gives up after seeing `limit` distinct states.
+pub fn solve(level: &Level, inventory: &Inventory, limit: usize) -> Solution {
+    let initial = level.map.as_standard_layout().into_owned();
+    let mut scratch = level.clone();
+    scratch.map = initial.clone();
+
+    let start = State::new(&initial, &scratch, inventory);
+    // For every state seen, the state it was reached from and the move taken.
+    let mut parents: Vec<Option<(usize, Dir)>> = vec![None];
+    let mut seen = HashSet::default();
+    seen.insert(start.clone());
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((state, index)) = queue.pop_front() {
+        for dir in Dir::ALL {
+            let mut inventory = state.restore(&initial, &mut scratch);
+            let outcome = scratch.step(dir, &mut inventory);
+            if outcome.death.is_some() || (!outcome.moved && outcome.changed.is_empty()) {
+                continue;
+            }
+            if outcome.complete {
+                let mut moves = vec![dir];
+                let mut current = index;
+                while let Some((parent, dir)) = parents[current] {
+                    moves.push(dir);
+                    current = parent;
+                }
+                moves.reverse();
+                return Solution::Solved(moves);
+            }
+
+            let next = State::new(&initial, &scratch, &inventory);
+            if seen.contains(&next) {
+                continue;
+            }
+            if seen.len() >= limit {
+                return Solution::GaveUp(seen.len());
+            }
+            let next_index = parents.len();
+            parents.push(Some((index, dir)));
+            seen.insert(next.clone());
+            queue.push_back((next, next_index));
+        }
+    }
+    Solution::Unsolvable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::tests::level;
+
+    #[test]
+    fn solves_with_the_fewest_moves() {
+        let level = level(&["s w", "  e"]);
+        let Solution::Solved(moves) = solve(&level, &Inventory::default(), 1000) else {
+            panic!("the exit is reachable");
+        };
+        assert_eq!(moves.len(), 3);
+    }
+
+    #[test]
+    fn knows_when_there_is_no_way_out() {
+        let level = level(&["swe"]);
+        assert_eq!(
+            solve(&level, &Inventory::default(), 1000),
+            Solution::Unsolvable
+        );
+    }
+
+    #[test]
+    fn gives_up_at_the_limit() {
+        let level = level(&["s   e"]);
+        assert_eq!(solve(&level, &Inventory::default(), 2), Solution::GaveUp(2));
+    }
+
+    #[test]
+    fn uses_what_it_picks_up() {
+        // The hole can only be crossed with the cement from behind the player.
+        let level = level(&["csh e"]);
+        let Solution::Solved(moves) = solve(&level, &Inventory::default(), 1000) else {
+            panic!("the cement fills the hole");
+        };
+        assert_eq!(moves[0], Dir::Left);
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::{
+    asset::{AssetLoader, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+// Named, as bevy's prelude has its own Audio and AudioSource.
+use bevy_kira_audio::{Audio, AudioControl, AudioSource};
+
+use crate::{
+    events::{Sound, SoundEvent},
+    settings::Settings,
+};
+
+use self::synth::Preset;
+
+mod synth;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<SoundPresets>()
+            .init_asset_loader::<SoundPresetsLoader>()
+            .insert_resource(SoundClips::default())
+            .add_startup_system(load_presets)
+            .add_system(synthesize_clips)
+            .add_system(play_sounds.after(synthesize_clips));
+    }
+}
+
+impl Sound {
+    pub const ALL: [Sound; 7] = [
+        Sound::PlayerDie,
+        Sound::HitWall,
+        Sound::Explosion,
+        Sound::Pickup,
+        Sound::Push,
+        Sound::Gunshot,
+        Sound::Teleport,
+    ];
+}
+
+/// How every sound is made, from a `.sfx.ron` file.
+#[derive(Debug, Clone, Default, PartialEq, TypeUuid, Deref)]
+#[uuid = "3c0f3e52-81a4-4b5e-9d2c-7a61f0b8e4d9"]
+pub struct SoundPresets(HashMap<Sound, Preset>);
+
+#[derive(Debug, Clone, Default, Copy)]
+pub struct SoundPresetsLoader;
+
+impl AssetLoader for SoundPresetsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let presets = ron::de::from_bytes(bytes).map_err(|e| {
+                bevy::asset::Error::msg(format!("Error loading sound presets: {e}"))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(SoundPresets(presets)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sfx.ron"]
+    }
+}
+
+/// The presets, and a clip made from each.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SoundClips {
+    presets: Handle<SoundPresets>,
+    clips: HashMap<Sound, Handle<AudioSource>>,
+}
+
+/// Loaded outside the loading state, so broken presets only leave the game
+/// quiet instead of stopping it from starting.
+fn load_presets(asset_server: Res<AssetServer>, mut clips: ResMut<SoundClips>) {
+    clips.presets = asset_server.load("sounds/presets.sfx.ron");
+}
+
+/// Makes the clips once the presets load, and again whenever they are saved,
+/// so sounds can be tweaked while the game runs.
+fn synthesize_clips(
+    mut asset_events: EventReader<AssetEvent<SoundPresets>>,
+    presets: Res<Assets<SoundPresets>>,
+    mut sources: ResMut<Assets<AudioSource>>,
+    mut clips: ResMut<SoundClips>,
+) {
+    let changed = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            *handle == clips.presets
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    let Some(presets) = presets.get(&clips.presets).filter(|_| changed) else {
+        return;
+    };
+    clips.clips.clear();
+    for sound in Sound::ALL {
+        let Some(preset) = presets.get(&sound) else {
+            log::warn!("No preset for {sound:?}, it will not be heard");
+            continue;
+        };
+        let clip = sources.add(AudioSource {
+            sound: preset.synthesize(),
+        });
+        clips.clips.insert(sound, clip);
+    }
+}
+
+fn play_sounds(
+    mut sound_events: EventReader<SoundEvent>,
+    clips: Res<SoundClips>,
+    settings: Res<Settings>,
+    audio: Res<Audio>,
+) {
+    let mut played = Vec::new();
+    for SoundEvent { sound } in sound_events.iter() {
+        // A chain of explosions is still one bang.
+        if played.contains(sound) {
+            continue;
+        }
+        played.push(*sound);
+        if let Some(clip) = clips.clips.get(sound) {
+            audio
+                .play(clip.clone())
+                .with_volume(settings.volume as f64 / 100.0);
+        }
+    }
+}
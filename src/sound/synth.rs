@@ -0,0 +1,251 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use kira::{
+    dsp::Frame,
+    sound::static_sound::{StaticSoundData, StaticSoundSettings},
+};
+use serde::{Deserialize, Serialize};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Samples of noise per period, so noise has a pitch like the other waves.
+const NOISE_SAMPLES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Wave {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+/// How to make one sound, after the sfxr generator. Times are in seconds and
+/// pitches in Hz. Anything left out of a preset keeps its default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub wave: Wave,
+    pub attack: f32,
+    pub sustain: f32,
+    /// Extra loudness at the start of the sustain, from 0 to 1.
+    pub punch: f32,
+    pub decay: f32,
+    pub frequency: f32,
+    /// The sound ends early if it slides below this pitch.
+    pub min_frequency: f32,
+    /// Pitch change, in octaves per second.
+    pub slide: f32,
+    /// Change to the slide, in octaves per second per second.
+    pub delta_slide: f32,
+    /// How far the pitch wobbles, as a fraction of it.
+    pub vibrato_depth: f32,
+    pub vibrato_speed: f32,
+    /// The pitch is multiplied by this once `arpeggio_time` has passed.
+    pub arpeggio: f32,
+    pub arpeggio_time: f32,
+    /// How much of each period a square wave is high, from 0 to 1.
+    pub duty: f32,
+    /// Change to the duty, per second.
+    pub duty_sweep: f32,
+    /// Cutoff for a low-pass filter, off when 0.
+    pub low_pass: f32,
+    /// Cutoff for a high-pass filter, off when 0.
+    pub high_pass: f32,
+    pub volume: f32,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            wave: Wave::Square,
+            attack: 0.0,
+            sustain: 0.1,
+            punch: 0.0,
+            decay: 0.2,
+            frequency: 440.0,
+            min_frequency: 20.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arpeggio: 1.0,
+            arpeggio_time: 0.0,
+            duty: 0.5,
+            duty_sweep: 0.0,
+            low_pass: 0.0,
+            high_pass: 0.0,
+            volume: 0.5,
+        }
+    }
+}
+
+/// A tiny xorshift generator, so noise sounds the same every run without
+/// pulling in a crate for it.
+struct Noise(u32);
+
+impl Noise {
+    /// Between -1 and 1.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn fill(&mut self, buffer: &mut [f32]) {
+        for sample in buffer {
+            *sample = self.next();
+        }
+    }
+}
+
+/// A one-pole filter's smoothing factor for `cutoff`.
+fn smoothing(cutoff: f32) -> f32 {
+    let dt = 1.0 / SAMPLE_RATE as f32;
+    let rc = 1.0 / (TAU * cutoff);
+    dt / (rc + dt)
+}
+
+impl Preset {
+    fn envelope(&self, t: f32) -> f32 {
+        if t < self.attack {
+            t / self.attack
+        } else if t < self.attack + self.sustain {
+            let into = (t - self.attack) / self.sustain;
+            1.0 + self.punch * (1.0 - into)
+        } else {
+            let into = (t - self.attack - self.sustain) / self.decay.max(f32::EPSILON);
+            (1.0 - into).max(0.0)
+        }
+    }
+
+    /// Renders the sound as mono samples.
+    pub fn samples(&self) -> Vec<f32> {
+        let dt = 1.0 / SAMPLE_RATE as f32;
+        let length = ((self.attack + self.sustain + self.decay) * SAMPLE_RATE as f32) as usize;
+        let mut noise = Noise(0x9e37_79b9);
+        let mut noise_buffer = [0.0; NOISE_SAMPLES];
+        noise.fill(&mut noise_buffer);
+
+        let mut samples = Vec::with_capacity(length);
+        let mut frequency = self.frequency;
+        let mut slide = self.slide;
+        let mut duty = self.duty;
+        let mut arpeggio_done = self.arpeggio_time <= 0.0;
+        let mut phase = 0.0_f32;
+        let mut low = 0.0;
+        let mut high_in = 0.0;
+        let mut high = 0.0;
+        for i in 0..length {
+            let t = i as f32 * dt;
+            if !arpeggio_done && t >= self.arpeggio_time {
+                frequency *= self.arpeggio;
+                arpeggio_done = true;
+            }
+            frequency *= (slide * dt).exp2();
+            slide += self.delta_slide * dt;
+            if frequency < self.min_frequency {
+                break;
+            }
+            duty = (duty + self.duty_sweep * dt).clamp(0.0, 1.0);
+
+            let vibrato = 1.0 + self.vibrato_depth * (TAU * self.vibrato_speed * t).sin();
+            phase += frequency * vibrato * dt;
+            if phase >= 1.0 {
+                phase = phase.fract();
+                // New noise each period, as sfxr does.
+                if self.wave == Wave::Noise {
+                    noise.fill(&mut noise_buffer);
+                }
+            }
+            let mut sample = match self.wave {
+                Wave::Square if phase < duty => 0.5,
+                Wave::Square => -0.5,
+                Wave::Sawtooth => 1.0 - phase * 2.0,
+                Wave::Sine => (TAU * phase).sin(),
+                Wave::Noise => noise_buffer[(phase * NOISE_SAMPLES as f32) as usize],
+            };
+
+            if self.low_pass > 0.0 {
+                low += smoothing(self.low_pass) * (sample - low);
+                sample = low;
+            }
+            if self.high_pass > 0.0 {
+                let alpha = 1.0 - smoothing(self.high_pass);
+                high = alpha * (high + sample - high_in);
+                high_in = sample;
+                sample = high;
+            }
+            samples.push((sample * self.envelope(t) * self.volume).clamp(-1.0, 1.0));
+        }
+        samples
+    }
+
+    pub fn synthesize(&self) -> StaticSoundData {
+        StaticSoundData {
+            sample_rate: SAMPLE_RATE,
+            frames: Arc::new(self.samples().into_iter().map(Frame::from_mono).collect()),
+            settings: StaticSoundSettings::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lasts_as_long_as_its_envelope() {
+        let preset = Preset {
+            attack: 0.1,
+            sustain: 0.2,
+            decay: 0.3,
+            ..Default::default()
+        };
+        let seconds = preset.samples().len() as f32 / SAMPLE_RATE as f32;
+        assert!((seconds - 0.6).abs() < 0.001, "{seconds}");
+    }
+
+    #[test]
+    fn ends_once_it_slides_below_the_minimum() {
+        // Halves every 0.1s, so it passes 100Hz after 0.2s.
+        let preset = Preset {
+            frequency: 400.0,
+            min_frequency: 100.0,
+            slide: -10.0,
+            sustain: 1.0,
+            ..Default::default()
+        };
+        let seconds = preset.samples().len() as f32 / SAMPLE_RATE as f32;
+        assert!((seconds - 0.2).abs() < 0.001, "{seconds}");
+    }
+
+    #[test]
+    fn stays_in_range() {
+        for wave in [Wave::Square, Wave::Sawtooth, Wave::Sine, Wave::Noise] {
+            let preset = Preset {
+                wave,
+                punch: 1.0,
+                volume: 1.0,
+                high_pass: 200.0,
+                ..Default::default()
+            };
+            let samples = preset.samples();
+            assert!(!samples.is_empty());
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)), "{wave:?}");
+        }
+    }
+
+    #[test]
+    fn bundled_presets_all_sound() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/sounds/presets.sfx.ron");
+        let text = std::fs::read_to_string(path).unwrap();
+        let presets: std::collections::HashMap<crate::events::Sound, Preset> =
+            ron::from_str(&text).unwrap();
+        for sound in crate::events::Sound::ALL {
+            let samples = presets[&sound].samples();
+            assert!(!samples.is_empty(), "{sound:?}");
+            assert!(samples.iter().all(|s| s.is_finite()), "{sound:?}");
+        }
+    }
+}
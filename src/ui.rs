@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_asset_loader::prelude::{AssetCollection, LoadingStateAppExt};
+
+use crate::{
+    events::{DeathMessage, LevelEvent, LevelSummary, MenuEvent},
+    level::{ActivePack, Levels, LoadError},
+    GameState,
+};
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_collection_to_loading_state::<_, UiAssets>(GameState::Loading)
+            .add_system(show_error.in_schedule(OnEnter(GameState::Error)))
+            .add_system(error_input.in_set(OnUpdate(GameState::Error)))
+            .add_system(show_game_over.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(close_screen.in_schedule(OnExit(GameState::GameOver)))
+            .add_system(show_level_complete.in_schedule(OnEnter(GameState::LevelComplete)))
+            .add_system(close_screen.in_schedule(OnExit(GameState::LevelComplete)))
+            .add_system(next_level.in_set(OnUpdate(GameState::LevelComplete)));
+    }
+}
+
+#[derive(Debug, Clone, Default, Resource, AssetCollection)]
+pub struct UiAssets {
+    #[asset(path = "fonts/DejaVuSans.ttf")]
+    pub font: Handle<Font>,
+}
+
+/// A full screen message, removed when its state ends.
+#[derive(Debug, Copy, Clone, Component)]
+struct Screen;
+
+fn spawn_screen(
+    commands: &mut Commands,
+    ui_assets: &UiAssets,
+    background: Color,
+    title: &str,
+    body: String,
+    hint: &str,
+) {
+    let style = |font_size| TextStyle {
+        font: ui_assets.font.clone(),
+        font_size,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            Screen,
+            NodeBundle {
+                style: Style {
+                    size: Size::all(Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                background_color: background.into(),
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_sections([
+                    TextSection::new(format!("{title}\n\n"), style(32.0)),
+                    TextSection::new(body, style(20.0)),
+                    TextSection::new(format!("\n\n{hint}"), style(20.0)),
+                ])
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style {
+                    max_size: Size::width(Val::Percent(90.0)),
+                    ..Default::default()
+                }),
+            );
+        });
+}
+
+fn close_screen(mut commands: Commands, screens: Query<Entity, With<Screen>>) {
+    for screen in &screens {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn show_error(mut commands: Commands, error: Res<LoadError>, ui_assets: Res<UiAssets>) {
+    spawn_screen(
+        &mut commands,
+        &ui_assets,
+        Color::rgb(0.2, 0.05, 0.05),
+        "Could not start the level pack",
+        error.0.clone(),
+        "Press Escape to quit.",
+    );
+}
+
+fn error_input(input: Res<Input<KeyCode>>, mut exit: EventWriter<AppExit>) {
+    if input.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit);
+    }
+}
+
+fn show_game_over(mut commands: Commands, message: Res<DeathMessage>, ui_assets: Res<UiAssets>) {
+    spawn_screen(
+        &mut commands,
+        &ui_assets,
+        Color::rgba(0.3, 0.0, 0.0, 0.7),
+        "Game Over",
+        message.0.clone(),
+        "Press R to restart.",
+    );
+}
+
+fn show_level_complete(
+    mut commands: Commands,
+    summary: Res<LevelSummary>,
+    ui_assets: Res<UiAssets>,
+) {
+    let items = if summary.items.is_empty() {
+        "No items left.".to_string()
+    } else {
+        let items: Vec<_> = summary
+            .items
+            .iter()
+            .map(|(item, count)| format!("{item:?} x{count}"))
+            .collect();
+        format!("Items left: {}.", items.join(", "))
+    };
+    spawn_screen(
+        &mut commands,
+        &ui_assets,
+        Color::rgba(0.0, 0.15, 0.05, 0.7),
+        &format!("Level {} complete", summary.number),
+        format!(
+            "{}\n\nFinished in {} moves{}, with {} pushes and {} items used, in {}. {items}",
+            summary.name,
+            summary.moves,
+            compare_to_par(summary.moves, summary.par),
+            summary.pushes,
+            summary.items_used,
+            format_time(summary.time)
+        ),
+        "Press Enter to continue.",
+    );
+}
+
+fn compare_to_par(moves: usize, par: Option<usize>) -> String {
+    match par {
+        None => String::new(),
+        Some(par) if moves < par => format!(" ({} under par {par})", par - moves),
+        Some(par) if moves == par => format!(" (right on par {par})"),
+        Some(par) => format!(" ({} over par {par})", moves - par),
+    }
+}
+
+/// Minutes and seconds, like `1:05`.
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Moves on from the level complete screen, back to the menu after the last
+/// level in the pack.
+fn next_level(
+    mut menu_events: EventReader<MenuEvent>,
+    summary: Res<LevelSummary>,
+    pack: Res<ActivePack>,
+    levels: Res<Assets<Levels>>,
+    mut level_events: EventWriter<LevelEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !menu_events.iter().any(|event| *event == MenuEvent::Select) {
+        return;
+    }
+    let next = summary.number + 1;
+    if levels
+        .get(&pack)
+        .is_some_and(|levels| next < levels.levels.len())
+    {
+        level_events.send(LevelEvent(next));
+        next_state.set(GameState::Playing);
+    } else {
+        log::info!("Finished the last level in the pack");
+        next_state.set(GameState::Menu);
+    }
+}
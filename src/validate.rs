@@ -0,0 +1,180 @@
+use std::{fs, path::Path};
+
+use bevy::utils::HashMap;
+use itertools::Itertools;
+
+use crate::{
+    level::{Cell, Inventory, PackFormat, RawLevel},
+    solver::{solve, Solution},
+};
+
+/// Checks every level in the pack at `path`, printing what it finds to stdout
+/// and anything wrong to stderr. Returns whether every level was shown to be
+/// playable, so a level the solver gave up on counts against the pack.
+pub fn validate_pack(path: &Path, max_states: usize) -> bool {
+    let Some(format) = PackFormat::from_path(path) else {
+        eprintln!(
+            "{}: expected a .dat, .pack.ron or .pack.json file",
+            path.display()
+        );
+        return false;
+    };
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            return false;
+        }
+    };
+    let (_, levels) = match format.parse_raw(&bytes) {
+        Ok(levels) => levels,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            return false;
+        }
+    };
+
+    validate_levels(levels, max_states)
+}
+
+fn validate_levels(levels: Vec<RawLevel>, max_states: usize) -> bool {
+    let mut valid = true;
+    for (number, level) in levels.into_iter().enumerate() {
+        let name = format!("{number:3} {}", level.name);
+        for note in notes(&level) {
+            println!("{name}: note, {note}");
+        }
+        let problems = problems(&level);
+        if !problems.is_empty() {
+            valid = false;
+            eprintln!("{name}: {}", problems.join(", "));
+            continue;
+        }
+
+        let level = level.into_level(number).expect("checked for a start above");
+        match solve(&level, &Inventory::default(), max_states) {
+            Solution::Solved(moves) => println!("{name}: ok, solvable in {} moves", moves.len()),
+            Solution::Unsolvable => {
+                valid = false;
+                eprintln!("{name}: cannot be solved");
+            }
+            Solution::GaveUp(states) => {
+                valid = false;
+                eprintln!(
+                    "{name}: inconclusive, no solution found in {states} positions \
+                     (raise --max-states to search further)"
+                )
+            }
+        }
+    }
+    valid
+}
+
+/// Things worth knowing about a level that still leave it playable.
+fn notes(level: &RawLevel) -> Vec<String> {
+    // Short rows are padded with empty squares, see RawLevel::into_level
+    let width = level.rows.iter().map(Vec::len).max().unwrap_or(0);
+    level
+        .rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| row.len() != width)
+        .map(|(j, row)| {
+            format!(
+                "row {j} is {} cells wide instead of {width}, the rest is empty",
+                row.len()
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn problems(level: &RawLevel) -> Vec<String> {
+    let mut problems = vec![];
+    let cells = || level.rows.iter().flatten();
+
+    match cells().filter(|c| c == &&Cell::Start).count() {
+        1 => {}
+        0 => problems.push("no start".to_string()),
+        n => problems.push(format!("{n} starts")),
+    }
+    if !cells().any(|c| c == &Cell::Exit) {
+        problems.push("no exit".to_string());
+    }
+
+    let mut teleports = HashMap::<u8, usize>::default();
+    for cell in cells() {
+        if let Cell::Teleport(id, _) = cell {
+            *teleports.entry(*id).or_insert(0) += 1;
+        }
+    }
+    for (id, count) in teleports.into_iter().sorted() {
+        // Cell::indices only has sprites for these
+        if !(1..=5).contains(&id) {
+            problems.push(format!("teleporter {id} has no sprite"));
+        }
+        // Any more than one make a ring, see Level::teleport_partner
+        if count < 2 {
+            problems.push(format!("teleporter {id} has nowhere to send the player"));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::parse_row;
+
+    fn raw(rows: &[&str]) -> RawLevel {
+        RawLevel {
+            rows: rows.iter().map(|row| parse_row(row).unwrap()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_playable_level_has_no_problems() {
+        let level = raw(&["s t14", "  t14e"]);
+        assert!(problems(&level).is_empty());
+        assert!(validate_levels(vec![level], 1000));
+    }
+
+    #[test]
+    fn needs_exactly_one_start() {
+        assert_eq!(problems(&raw(&["  e"])), vec!["no start"]);
+        assert_eq!(problems(&raw(&["s se"])), vec!["2 starts"]);
+    }
+
+    #[test]
+    fn needs_an_exit() {
+        assert_eq!(problems(&raw(&["s  "])), vec!["no exit"]);
+    }
+
+    #[test]
+    fn teleporters_need_a_partner() {
+        assert_eq!(
+            problems(&raw(&["st12e"])),
+            vec!["teleporter 1 has nowhere to send the player"]
+        );
+    }
+
+    #[test]
+    fn short_rows_are_only_noted() {
+        let level = raw(&["s  ", "e"]);
+        assert!(problems(&level).is_empty());
+        assert_eq!(notes(&level).len(), 1);
+        assert!(validate_levels(vec![level], 1000));
+    }
+
+    #[test]
+    fn an_unsolvable_level_fails() {
+        let level = raw(&["swe"]);
+        assert!(problems(&level).is_empty());
+        assert!(!validate_levels(vec![level], 1000));
+    }
+
+    #[test]
+    fn an_inconclusive_level_fails() {
+        assert!(!validate_levels(vec![raw(&["s   e"])], 2));
+    }
+}